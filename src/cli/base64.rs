@@ -1,4 +1,4 @@
-use clap::{arg, Parser, Subcommand, ValueEnum};
+use clap::{Parser, Subcommand, ValueEnum};
 use enum_dispatch::enum_dispatch;
 
 use crate::{process_decode, process_encode, CmdExector};
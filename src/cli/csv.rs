@@ -1,51 +1,1027 @@
-use std::fmt::Display;
+use std::{
+    fmt::Display,
+    io::{IsTerminal, Write},
+    path::{Path, PathBuf},
+};
 
-use clap::{Parser, ValueEnum};
+use clap::{ArgAction, Args, Parser, Subcommand, ValueEnum};
+use encoding_rs::Encoding;
+use enum_dispatch::enum_dispatch;
 
-use crate::{process_csv, CmdExector};
+use crate::{
+    get_writer, print_paged, process_csv, process_csv_cat, process_csv_diff, process_csv_join,
+    process_csv_melt, process_csv_pivot, process_csv_protect, process_csv_query, process_csv_show,
+    process_csv_sort, process_csv_split, process_csv_stats, process_csv_stream,
+    process_csv_unprotect, process_csv_validate, render_diff, render_patch, render_stats,
+    table_name, write_records, CmdExector, CsvDialect, CsvReadOpts, CsvSchema, CsvWriteOpts,
+    PivotSpec, RowTransform, SortSpec, SplitBy, TableStyle, TextEncoding,
+};
 
-use super::verify_file;
+use super::{parse_delimiter, parse_encoding, parse_output_encoding, verify_file};
 
-#[derive(Debug, Clone, Copy, ValueEnum)]
+#[derive(Debug, Clone, Copy, PartialEq, Eq, ValueEnum)]
+pub enum InputFormat {
+    Csv,
+    Json,
+    Ndjson,
+    Yaml,
+    Toml,
+    Xlsx,
+    Ods,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, ValueEnum)]
 pub enum OutputFormat {
     Json,
     Yaml,
+    Toml,
+    Ndjson,
+    Csv,
+    Sql,
+    Sqlite,
+    Markdown,
+    Html,
+    Xlsx,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, ValueEnum)]
+pub enum ReportFormat {
+    Text,
+    Json,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, ValueEnum)]
+pub enum JoinKind {
+    Inner,
+    Left,
+    Full,
 }
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, ValueEnum)]
+pub enum Aggregate {
+    Count,
+    Sum,
+    Avg,
+    Min,
+    Max,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, ValueEnum)]
+pub enum ProtectMethod {
+    /// keyed blake3 hashes, joinable but not reversible
+    Pseudonym,
+    /// chacha20-poly1305 ciphertext, reversible with unprotect
+    Encrypt,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, ValueEnum)]
+pub enum DiffFormat {
+    Human,
+    Json,
+    Patch,
+}
+
+impl InputFormat {
+    /// Guess the input format from the file extension, falling back to csv.
+    pub fn detect(input: &str) -> Self {
+        let ext = Path::new(input)
+            .extension()
+            .and_then(|ext| ext.to_str())
+            .map(|ext| ext.to_ascii_lowercase());
+        match ext.as_deref() {
+            Some("json") => InputFormat::Json,
+            Some("ndjson") | Some("jsonl") => InputFormat::Ndjson,
+            Some("yaml") | Some("yml") => InputFormat::Yaml,
+            Some("toml") => InputFormat::Toml,
+            Some("xlsx") | Some("xlsm") | Some("xlsb") | Some("xls") => InputFormat::Xlsx,
+            Some("ods") => InputFormat::Ods,
+            _ => InputFormat::Csv,
+        }
+    }
+
+    /// Whether records come in rows of flat cells, like csv and spreadsheets.
+    pub fn is_tabular(&self) -> bool {
+        matches!(self, Self::Csv | Self::Xlsx | Self::Ods)
+    }
+}
+
 impl Display for OutputFormat {
     fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
         let t = match self {
             OutputFormat::Json => "json",
             OutputFormat::Yaml => "yaml",
+            OutputFormat::Toml => "toml",
+            OutputFormat::Ndjson => "ndjson",
+            OutputFormat::Csv => "csv",
+            OutputFormat::Sql => "sql",
+            OutputFormat::Sqlite => "sqlite",
+            OutputFormat::Markdown => "md",
+            OutputFormat::Html => "html",
+            OutputFormat::Xlsx => "xlsx",
         };
         write!(f, "{}", t)
     }
 }
 
 #[derive(Debug, Parser)]
+#[command(args_conflicts_with_subcommands = true, subcommand_negates_reqs = true)]
 pub struct CsvOpts {
+    #[command(subcommand)]
+    pub cmd: Option<CsvSubCommand>,
+
+    #[command(flatten)]
+    pub convert: Option<CsvConvertOpts>,
+}
+
+impl CmdExector for CsvOpts {
+    async fn execute(self) -> anyhow::Result<()> {
+        match (self.cmd, self.convert) {
+            (Some(cmd), _) => cmd.execute().await,
+            (None, Some(convert)) => convert.execute().await,
+            (None, None) => unreachable!("clap requires a subcommand or conversion args"),
+        }
+    }
+}
+
+#[derive(Debug, Subcommand)]
+#[enum_dispatch(CmdExector)]
+pub enum CsvSubCommand {
+    #[command(about = "Show CSV as an aligned table")]
+    Show(CsvShowOpts),
+    #[command(
+        about = "Query CSV with SQL, eg: SELECT Name FROM juventus WHERE \"Kit Number\" < 10"
+    )]
+    Query(CsvQueryOpts),
+    #[command(about = "Profile every column of a CSV file")]
+    Stats(CsvStatsOpts),
+    #[command(about = "Validate CSV against a schema")]
+    Validate(CsvValidateOpts),
+    #[command(about = "Show rows added, removed and modified between two CSV files")]
+    Diff(CsvDiffOpts),
+    #[command(about = "Join two files on key columns")]
+    Join(CsvJoinOpts),
+    #[command(about = "Concatenate files with the same columns in any order")]
+    Cat(CsvCatOpts),
+    #[command(about = "Turn the values of one column into columns, aggregating another")]
+    Pivot(CsvPivotOpts),
+    #[command(about = "Turn columns into variable/value rows, the reverse of pivot")]
+    Melt(CsvMeltOpts),
+    #[command(about = "Sort a CSV file of any size by key columns")]
+    Sort(CsvSortOpts),
+    #[command(about = "Drop rows repeating a key, keeping the first one, sorted by key")]
+    Dedup(CsvDedupOpts),
+    #[command(about = "Split a CSV file into chunks of N rows or one file per column value")]
+    Split(CsvSplitOpts),
+    #[command(about = "Replace columns with keyed pseudonyms or encrypt them")]
+    Protect(CsvProtectOpts),
+    #[command(about = "Decrypt columns encrypted by protect")]
+    Unprotect(CsvUnprotectOpts),
+}
+
+/// How to read the csv input, shared by every csv command.
+#[derive(Debug, Args)]
+pub struct CsvDialectOpts {
+    #[arg(short, long, value_parser = parse_delimiter, default_value = ",", help = "field delimiter, use \\t for tabs")]
+    pub delimiter: u8,
+
+    #[arg(long, default_value_t = true, action = ArgAction::Set, help = "whether the first row is a header, columns are named column1, column2... if not")]
+    pub header: bool,
+
+    #[arg(
+        long,
+        help = "sniff delimiter, quote character and header from the file"
+    )]
+    pub auto: bool,
+
+    #[arg(long, value_parser = parse_encoding, default_value = "utf-8", help = "input encoding, eg: gbk, shift_jis, utf-16, latin1, or auto to detect it")]
+    pub encoding: TextEncoding,
+
+    #[arg(
+        long,
+        help = "sheet to read from an xlsx/ods file, by name or 1-based position"
+    )]
+    pub sheet: Option<String>,
+}
+
+impl CsvDialectOpts {
+    pub fn dialect(&self) -> CsvDialect {
+        CsvDialect {
+            delimiter: self.delimiter,
+            has_headers: self.header,
+            auto: self.auto,
+            encoding: self.encoding,
+            sheet: self.sheet.clone(),
+            ..Default::default()
+        }
+    }
+}
+
+#[derive(Debug, Args)]
+// clap leaves the group of a struct with flattened args empty, so name the required ones that
+// tell a conversion apart from a bare `rcli csv`
+#[group(args = ["input", "format"])]
+pub struct CsvConvertOpts {
     #[arg(short, long, value_parser = verify_file)]
     pub input: String,
 
     #[arg(short, long)]
     pub output: Option<String>,
 
+    #[arg(
+        value_enum,
+        long,
+        help = "input format, detected from the file extension if omitted"
+    )]
+    pub input_format: Option<InputFormat>,
+
     #[arg(value_enum, long)]
     pub format: OutputFormat,
 
-    #[arg(short, long, default_value_t = ',')]
-    delimiter: char,
+    #[arg(long, help = "replace the sqlite output file if it exists")]
+    pub force: bool,
+
+    #[arg(long, value_parser = verify_file, help = "yaml schema forcing column types and renames")]
+    pub schema: Option<String>,
+
+    #[arg(
+        long,
+        help = "keep every csv value as a string instead of inferring types"
+    )]
+    pub no_infer: bool,
 
-    #[arg(long, default_value_t = true)]
-    header: bool,
+    #[arg(
+        long,
+        help = "convert row by row with flat memory use, types are inferred per cell"
+    )]
+    pub stream: bool,
+
+    #[arg(long, help = "report streaming progress on stderr")]
+    pub progress: bool,
+
+    #[arg(long, value_parser = parse_output_encoding, default_value = "utf-8", help = "output encoding, utf-16 output starts with a BOM")]
+    pub output_encoding: &'static Encoding,
+
+    #[arg(long, help = "make html tables sortable by clicking a header")]
+    pub sortable: bool,
+
+    #[arg(
+        long,
+        help = "keep dotted and indexed csv headers like address.city as flat keys"
+    )]
+    pub no_nest: bool,
+
+    #[command(flatten)]
+    pub dialect: CsvDialectOpts,
+
+    #[arg(
+        long = "with",
+        value_name = "NAME = EXPR",
+        help = "add or replace a column computed from the row, eg: 'age = 2024 - year(DOB)'"
+    )]
+    pub with: Vec<String>,
+
+    #[arg(
+        long = "where",
+        value_name = "EXPR",
+        help = "keep only the rows matching an expression, eg: 'Kit Number > 10'"
+    )]
+    pub filter: Option<String>,
 }
 
-impl CmdExector for CsvOpts {
+impl CmdExector for CsvConvertOpts {
     async fn execute(self) -> anyhow::Result<()> {
         let output = if let Some(output) = &self.output {
             output.clone()
         } else {
             format!("output.{}", self.format)
         };
-        process_csv(&self.input, output, self.format)
+        let input_format = self
+            .input_format
+            .unwrap_or_else(|| InputFormat::detect(&self.input));
+        let schema = self.schema.as_deref().map(CsvSchema::load).transpose()?;
+        let opts = CsvReadOpts {
+            infer: !self.no_infer,
+            schema,
+            dialect: self.dialect.dialect(),
+            transform: RowTransform {
+                with: self.with,
+                filter: self.filter,
+            },
+        };
+        let write_opts = CsvWriteOpts {
+            encoding: self.output_encoding,
+            sortable: self.sortable,
+            nest: !self.no_nest,
+            force: self.force,
+        };
+        if self.stream {
+            if input_format != InputFormat::Csv {
+                anyhow::bail!("only csv input can be streamed");
+            }
+            return process_csv_stream(
+                &self.input,
+                &opts,
+                &output,
+                self.format,
+                &write_opts,
+                self.progress,
+            );
+        }
+        process_csv(
+            &self.input,
+            input_format,
+            &opts,
+            output,
+            self.format,
+            &write_opts,
+        )
+    }
+}
+
+#[derive(Debug, Parser)]
+pub struct CsvShowOpts {
+    #[arg(short, long, value_parser = verify_file)]
+    pub input: String,
+
+    #[arg(long, conflicts_with = "tail", help = "show only the first N rows")]
+    pub head: Option<usize>,
+
+    #[arg(long, help = "show only the last N rows")]
+    pub tail: Option<usize>,
+
+    #[arg(
+        short,
+        long,
+        value_delimiter = ',',
+        help = "columns to show, eg: Name,Position"
+    )]
+    pub columns: Vec<String>,
+
+    #[arg(
+        long,
+        default_value_t = 30,
+        help = "truncate cells wider than this, 0 to disable"
+    )]
+    pub max_width: usize,
+
+    #[arg(long, help = "print directly instead of going through $PAGER")]
+    pub no_pager: bool,
+
+    #[command(flatten)]
+    pub dialect: CsvDialectOpts,
+}
+
+impl CmdExector for CsvShowOpts {
+    async fn execute(self) -> anyhow::Result<()> {
+        let table = process_csv_show(
+            &self.input,
+            &self.dialect.dialect(),
+            self.head,
+            self.tail,
+            &self.columns,
+            self.max_width,
+            table_style(),
+        )?;
+        if self.no_pager {
+            print!("{}", table);
+            Ok(())
+        } else {
+            print_paged(&table)
+        }
+    }
+}
+
+#[derive(Debug, Parser)]
+pub struct CsvQueryOpts {
+    #[arg(help = "SELECT ... FROM table [WHERE ...] [GROUP BY ...] [ORDER BY ...] [LIMIT n]")]
+    pub sql: String,
+
+    #[arg(
+        short,
+        long,
+        value_parser = verify_file,
+        help = "csv file to query, the FROM table is read as a path if omitted"
+    )]
+    pub input: Option<String>,
+
+    #[arg(short, long, default_value = "-")]
+    pub output: String,
+
+    #[arg(value_enum, long, default_value_t = OutputFormat::Json)]
+    pub format: OutputFormat,
+
+    #[arg(long, help = "replace the sqlite output file if it exists")]
+    pub force: bool,
+
+    #[command(flatten)]
+    pub dialect: CsvDialectOpts,
+}
+
+impl CmdExector for CsvQueryOpts {
+    async fn execute(self) -> anyhow::Result<()> {
+        let rows = process_csv_query(&self.sql, self.input.as_deref(), &self.dialect.dialect())?;
+        write_records(
+            &rows,
+            self.format,
+            &self.output,
+            &table_name(self.input.as_deref().unwrap_or("-")),
+            self.force,
+        )
+    }
+}
+
+#[derive(Debug, Parser)]
+pub struct CsvStatsOpts {
+    #[arg(short, long, value_parser = verify_file)]
+    pub input: String,
+
+    #[arg(
+        long,
+        default_value_t = 5,
+        help = "number of most frequent values to report"
+    )]
+    pub top: usize,
+
+    #[arg(value_enum, long, default_value_t = ReportFormat::Text)]
+    pub format: ReportFormat,
+
+    #[command(flatten)]
+    pub dialect: CsvDialectOpts,
+}
+
+impl CmdExector for CsvStatsOpts {
+    async fn execute(self) -> anyhow::Result<()> {
+        let stats = process_csv_stats(&self.input, &self.dialect.dialect(), self.top)?;
+        match self.format {
+            ReportFormat::Text => print!("{}", render_stats(&stats, table_style())),
+            ReportFormat::Json => println!("{}", serde_json::to_string_pretty(&stats)?),
+        }
+        Ok(())
+    }
+}
+
+#[derive(Debug, Parser)]
+pub struct CsvValidateOpts {
+    #[arg(short, long, value_parser = verify_file)]
+    pub input: String,
+
+    #[arg(long, value_parser = verify_file, help = "yaml schema declaring the column rules")]
+    pub schema: String,
+
+    #[arg(value_enum, long, default_value_t = ReportFormat::Text)]
+    pub format: ReportFormat,
+
+    #[command(flatten)]
+    pub dialect: CsvDialectOpts,
+}
+
+impl CmdExector for CsvValidateOpts {
+    async fn execute(self) -> anyhow::Result<()> {
+        let schema = CsvSchema::load(&self.schema)?;
+        let violations = process_csv_validate(&self.input, &self.dialect.dialect(), &schema)?;
+        match self.format {
+            ReportFormat::Text => {
+                for violation in &violations {
+                    println!("{}", violation);
+                }
+            }
+            ReportFormat::Json => println!("{}", serde_json::to_string_pretty(&violations)?),
+        }
+        if !violations.is_empty() {
+            anyhow::bail!("{} violation(s) found in {}", violations.len(), self.input);
+        }
+        Ok(())
+    }
+}
+
+#[derive(Debug, Parser)]
+pub struct CsvDiffOpts {
+    #[arg(value_parser = verify_file, help = "the earlier version of the file")]
+    pub old: String,
+
+    #[arg(value_parser = verify_file, help = "the later version of the file")]
+    pub new: String,
+
+    #[arg(
+        short,
+        long,
+        required = true,
+        value_delimiter = ',',
+        help = "columns identifying a row, eg: Name or Name,DOB"
+    )]
+    pub key: Vec<String>,
+
+    #[arg(short, long, default_value = "-")]
+    pub output: String,
+
+    #[arg(value_enum, long, default_value_t = DiffFormat::Human)]
+    pub format: DiffFormat,
+
+    #[command(flatten)]
+    pub dialect: CsvDialectOpts,
+}
+
+impl CmdExector for CsvDiffOpts {
+    async fn execute(self) -> anyhow::Result<()> {
+        let diff = process_csv_diff(&self.old, &self.new, &self.dialect.dialect(), &self.key)?;
+        let res = match self.format {
+            DiffFormat::Human => render_diff(&diff),
+            DiffFormat::Json => serde_json::to_string_pretty(&diff)? + "\n",
+            DiffFormat::Patch => render_patch(&diff, &self.old, &self.new)?,
+        };
+        get_writer(&self.output)?.write_all(res.as_bytes())?;
+        Ok(())
+    }
+}
+
+#[derive(Debug, Parser)]
+pub struct CsvJoinOpts {
+    #[arg(value_parser = verify_file)]
+    pub left: String,
+
+    #[arg(value_parser = verify_file)]
+    pub right: String,
+
+    #[arg(
+        long,
+        required = true,
+        value_delimiter = ',',
+        help = "columns to join on, present in both files"
+    )]
+    pub on: Vec<String>,
+
+    #[arg(value_enum, long, default_value_t = JoinKind::Inner)]
+    pub how: JoinKind,
+
+    #[arg(short, long, default_value = "-")]
+    pub output: String,
+
+    #[arg(value_enum, long, default_value_t = OutputFormat::Csv)]
+    pub format: OutputFormat,
+
+    #[arg(long, help = "replace the sqlite output file if it exists")]
+    pub force: bool,
+
+    #[arg(
+        long,
+        help = "keep every csv value as a string instead of inferring types"
+    )]
+    pub no_infer: bool,
+
+    #[command(flatten)]
+    pub dialect: CsvDialectOpts,
+}
+
+impl CmdExector for CsvJoinOpts {
+    async fn execute(self) -> anyhow::Result<()> {
+        let opts = CsvReadOpts {
+            infer: !self.no_infer,
+            dialect: self.dialect.dialect(),
+            ..Default::default()
+        };
+        let rows = process_csv_join(&self.left, &self.right, &self.on, self.how, &opts)?;
+        write_records(
+            &rows,
+            self.format,
+            &self.output,
+            &table_name(&self.left),
+            self.force,
+        )
+    }
+}
+
+#[derive(Debug, Parser)]
+pub struct CsvCatOpts {
+    #[arg(value_parser = verify_file, required = true)]
+    pub inputs: Vec<String>,
+
+    #[arg(
+        long,
+        help = "allow differing columns, filling the missing ones with empty values"
+    )]
+    pub union: bool,
+
+    #[arg(short, long, default_value = "-")]
+    pub output: String,
+
+    #[arg(value_enum, long, default_value_t = OutputFormat::Csv)]
+    pub format: OutputFormat,
+
+    #[arg(long, help = "replace the sqlite output file if it exists")]
+    pub force: bool,
+
+    #[arg(
+        long,
+        help = "keep every csv value as a string instead of inferring types"
+    )]
+    pub no_infer: bool,
+
+    #[command(flatten)]
+    pub dialect: CsvDialectOpts,
+}
+
+impl CmdExector for CsvCatOpts {
+    async fn execute(self) -> anyhow::Result<()> {
+        let opts = CsvReadOpts {
+            infer: !self.no_infer,
+            dialect: self.dialect.dialect(),
+            ..Default::default()
+        };
+        let rows = process_csv_cat(&self.inputs, self.union, &opts)?;
+        write_records(
+            &rows,
+            self.format,
+            &self.output,
+            &table_name(&self.inputs[0]),
+            self.force,
+        )
+    }
+}
+
+#[derive(Debug, Parser)]
+pub struct CsvPivotOpts {
+    #[arg(short, long, value_parser = verify_file)]
+    pub input: String,
+
+    #[arg(
+        long,
+        required = true,
+        value_delimiter = ',',
+        help = "columns whose values become the output rows"
+    )]
+    pub rows: Vec<String>,
+
+    #[arg(long, help = "column whose values become the output columns")]
+    pub columns: String,
+
+    #[arg(long, help = "column to aggregate, rows are counted if omitted")]
+    pub values: Option<String>,
+
+    #[arg(value_enum, long, default_value_t = Aggregate::Count)]
+    pub agg: Aggregate,
+
+    #[arg(short, long, default_value = "-")]
+    pub output: String,
+
+    #[arg(value_enum, long, default_value_t = OutputFormat::Csv)]
+    pub format: OutputFormat,
+
+    #[arg(long, help = "replace the sqlite output file if it exists")]
+    pub force: bool,
+
+    #[arg(
+        long,
+        help = "keep every csv value as a string instead of inferring types"
+    )]
+    pub no_infer: bool,
+
+    #[command(flatten)]
+    pub dialect: CsvDialectOpts,
+}
+
+impl CmdExector for CsvPivotOpts {
+    async fn execute(self) -> anyhow::Result<()> {
+        let spec = PivotSpec {
+            rows: &self.rows,
+            column: &self.columns,
+            values: self.values.as_deref(),
+            aggregate: self.agg,
+            infer: !self.no_infer,
+        };
+        let rows = process_csv_pivot(&self.input, &self.dialect.dialect(), &spec)?;
+        write_records(
+            &rows,
+            self.format,
+            &self.output,
+            &table_name(&self.input),
+            self.force,
+        )
+    }
+}
+
+#[derive(Debug, Parser)]
+pub struct CsvMeltOpts {
+    #[arg(short, long, value_parser = verify_file)]
+    pub input: String,
+
+    #[arg(
+        long,
+        value_delimiter = ',',
+        help = "columns kept on every output row, eg: Name"
+    )]
+    pub id: Vec<String>,
+
+    #[arg(
+        long,
+        value_delimiter = ',',
+        help = "columns to melt, all but the id columns if omitted"
+    )]
+    pub value_columns: Vec<String>,
+
+    #[arg(
+        long,
+        default_value = "variable",
+        help = "name of the column holding column names"
+    )]
+    pub var_name: String,
+
+    #[arg(
+        long,
+        default_value = "value",
+        help = "name of the column holding the values"
+    )]
+    pub value_name: String,
+
+    #[arg(short, long, default_value = "-")]
+    pub output: String,
+
+    #[arg(value_enum, long, default_value_t = OutputFormat::Csv)]
+    pub format: OutputFormat,
+
+    #[arg(long, help = "replace the sqlite output file if it exists")]
+    pub force: bool,
+
+    #[arg(
+        long,
+        help = "keep every csv value as a string instead of inferring types"
+    )]
+    pub no_infer: bool,
+
+    #[command(flatten)]
+    pub dialect: CsvDialectOpts,
+}
+
+impl CmdExector for CsvMeltOpts {
+    async fn execute(self) -> anyhow::Result<()> {
+        let rows = process_csv_melt(
+            &self.input,
+            &self.dialect.dialect(),
+            &self.id,
+            &self.value_columns,
+            (&self.var_name, &self.value_name),
+            !self.no_infer,
+        )?;
+        write_records(
+            &rows,
+            self.format,
+            &self.output,
+            &table_name(&self.input),
+            self.force,
+        )
+    }
+}
+
+#[derive(Debug, Parser)]
+pub struct CsvSortOpts {
+    #[arg(short, long, value_parser = verify_file)]
+    pub input: String,
+
+    #[arg(
+        long,
+        required = true,
+        value_delimiter = ',',
+        help = "columns to sort by, eg: Position,Name"
+    )]
+    pub by: Vec<String>,
+
+    #[arg(long, help = "compare the key columns as numbers")]
+    pub numeric: bool,
+
+    #[arg(long, help = "sort in descending order")]
+    pub desc: bool,
+
+    #[arg(short, long, default_value = "-")]
+    pub output: String,
+
+    #[command(flatten)]
+    pub buffer: SortBufferOpts,
+
+    #[command(flatten)]
+    pub dialect: CsvDialectOpts,
+}
+
+impl CmdExector for CsvSortOpts {
+    async fn execute(self) -> anyhow::Result<()> {
+        let spec = SortSpec {
+            by: &self.by,
+            numeric: self.numeric,
+            desc: self.desc,
+            unique: false,
+            buffer_size: self.buffer.buffer_size * MB,
+            temp_dir: self.buffer.temp_dir.as_deref(),
+        };
+        let output = get_writer(&self.output)?;
+        process_csv_sort(&self.input, &self.dialect.dialect(), &spec, output)?;
+        Ok(())
+    }
+}
+
+#[derive(Debug, Parser)]
+pub struct CsvDedupOpts {
+    #[arg(short, long, value_parser = verify_file)]
+    pub input: String,
+
+    #[arg(
+        long,
+        value_delimiter = ',',
+        help = "columns identifying a row, the whole row if omitted"
+    )]
+    pub by: Vec<String>,
+
+    #[arg(short, long, default_value = "-")]
+    pub output: String,
+
+    #[command(flatten)]
+    pub buffer: SortBufferOpts,
+
+    #[command(flatten)]
+    pub dialect: CsvDialectOpts,
+}
+
+impl CmdExector for CsvDedupOpts {
+    async fn execute(self) -> anyhow::Result<()> {
+        let spec = SortSpec {
+            by: &self.by,
+            numeric: false,
+            desc: false,
+            unique: true,
+            buffer_size: self.buffer.buffer_size * MB,
+            temp_dir: self.buffer.temp_dir.as_deref(),
+        };
+        let output = get_writer(&self.output)?;
+        process_csv_sort(&self.input, &self.dialect.dialect(), &spec, output)?;
+        Ok(())
+    }
+}
+
+#[derive(Debug, Parser)]
+pub struct CsvSplitOpts {
+    #[arg(short, long, value_parser = verify_file)]
+    pub input: String,
+
+    #[arg(
+        long,
+        required_unless_present = "by",
+        conflicts_with = "by",
+        help = "rows per chunk"
+    )]
+    pub rows: Option<usize>,
+
+    #[arg(long, help = "column whose distinct values get a file each")]
+    pub by: Option<String>,
+
+    #[arg(
+        long,
+        help = "file name template, placeholders are stem, n and value in braces, eg: {stem}-{value}.csv"
+    )]
+    pub template: Option<String>,
+
+    #[arg(long, default_value = ".", help = "directory to write the chunks to")]
+    pub dir: PathBuf,
+
+    #[command(flatten)]
+    pub dialect: CsvDialectOpts,
+}
+
+impl CmdExector for CsvSplitOpts {
+    async fn execute(self) -> anyhow::Result<()> {
+        let split = match (self.rows, &self.by) {
+            (Some(rows), _) => SplitBy::Rows(rows),
+            (None, Some(by)) => SplitBy::Column(by),
+            (None, None) => unreachable!("clap requires --rows or --by"),
+        };
+        let template = self
+            .template
+            .as_deref()
+            .unwrap_or_else(|| split.default_template());
+        let chunks = process_csv_split(
+            &self.input,
+            &self.dialect.dialect(),
+            split,
+            template,
+            &self.dir,
+        )?;
+        for chunk in chunks {
+            println!("{}\t{}", chunk.path.display(), chunk.rows);
+        }
+        Ok(())
+    }
+}
+
+#[derive(Debug, Parser)]
+pub struct CsvProtectOpts {
+    #[arg(short, long, value_parser = verify_file)]
+    pub input: String,
+
+    #[arg(
+        long,
+        required = true,
+        value_delimiter = ',',
+        help = "columns to protect, eg: Name,DOB"
+    )]
+    pub columns: Vec<String>,
+
+    #[arg(long, value_parser = verify_file, help = "file holding a key of at least 32 bytes")]
+    pub key: String,
+
+    #[arg(value_enum, long, default_value_t = ProtectMethod::Pseudonym)]
+    pub method: ProtectMethod,
+
+    #[arg(short, long, default_value = "-")]
+    pub output: String,
+
+    #[command(flatten)]
+    pub dialect: CsvDialectOpts,
+}
+
+impl CmdExector for CsvProtectOpts {
+    async fn execute(self) -> anyhow::Result<()> {
+        process_csv_protect(
+            &self.input,
+            &self.dialect.dialect(),
+            &self.columns,
+            &self.key,
+            self.method,
+            get_writer(&self.output)?,
+        )
+    }
+}
+
+#[derive(Debug, Parser)]
+pub struct CsvUnprotectOpts {
+    #[arg(short, long, value_parser = verify_file)]
+    pub input: String,
+
+    #[arg(
+        long,
+        required = true,
+        value_delimiter = ',',
+        help = "encrypted columns to restore"
+    )]
+    pub columns: Vec<String>,
+
+    #[arg(long, value_parser = verify_file, help = "the key the columns were encrypted with")]
+    pub key: String,
+
+    #[arg(short, long, default_value = "-")]
+    pub output: String,
+
+    #[command(flatten)]
+    pub dialect: CsvDialectOpts,
+}
+
+impl CmdExector for CsvUnprotectOpts {
+    async fn execute(self) -> anyhow::Result<()> {
+        process_csv_unprotect(
+            &self.input,
+            &self.dialect.dialect(),
+            &self.columns,
+            &self.key,
+            get_writer(&self.output)?,
+        )
+    }
+}
+
+const MB: usize = 1024 * 1024;
+
+/// Memory and temporary files of the external sort behind sort and dedup.
+#[derive(Debug, Args)]
+pub struct SortBufferOpts {
+    #[arg(
+        long,
+        default_value_t = 256,
+        value_parser = clap::builder::RangedU64ValueParser::<usize>::new().range(1..),
+        help = "megabytes of rows sorted in memory before spilling them to a temporary file"
+    )]
+    pub buffer_size: usize,
+
+    #[arg(
+        long,
+        help = "directory for the temporary files, the system one if omitted"
+    )]
+    pub temp_dir: Option<PathBuf>,
+}
+
+/// Box drawing tables for terminals, plain columns for pipes.
+fn table_style() -> TableStyle {
+    if std::io::stdout().is_terminal() {
+        TableStyle::Unicode
+    } else {
+        TableStyle::Plain
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_detect_input_format() {
+        assert_eq!(InputFormat::detect("assets/juventus.csv"), InputFormat::Csv);
+        assert_eq!(InputFormat::detect("a.JSON"), InputFormat::Json);
+        assert_eq!(InputFormat::detect("a.jsonl"), InputFormat::Ndjson);
+        assert_eq!(InputFormat::detect("a.yml"), InputFormat::Yaml);
+        assert_eq!(InputFormat::detect("Cargo.toml"), InputFormat::Toml);
+        assert_eq!(InputFormat::detect("-"), InputFormat::Csv);
     }
 }
@@ -1,38 +1,382 @@
-use crate::{process_genpass, CmdExector};
-use clap::{arg, Parser};
-use zxcvbn::zxcvbn;
+use std::io::Read;
+
+use crate::{
+    generate_scored, get_reader, process_genpass, process_genpass_check, process_genphrase,
+    render_strength, write_records, CmdExector, OutputFormat, PassphraseSpec, PasswordPolicy,
+    ReportFormat, Wordlist,
+};
+use clap::{ArgGroup, Args, Parser, Subcommand};
+use enum_dispatch::enum_dispatch;
+use rand::SeedableRng;
+use rand_chacha::ChaCha20Rng;
+
+use super::verify_file;
+
+const SEED_WARNING: &str = "\
+WARNING: --seed makes every password reproducible by anyone who knows or guesses the seed.
+WARNING: use them for test fixtures only, never for real accounts.";
+
+const CLASS_FLAGS_WARNING: &str = "\
+WARNING: --uppercase, --lowercase, --number and --symbol are deprecated and ignored.
+WARNING: every character class is used by default, leave one out with --no-upper, --no-lower, --no-digits or --no-symbols.";
 
 #[derive(Debug, Parser)]
+#[command(
+    args_conflicts_with_subcommands = true,
+    group(
+        ArgGroup::new("policy_args")
+            .multiple(true)
+            .args(["length", "min_upper", "min_lower", "min_digits", "min_symbols", "symbols", "exclude", "no_upper", "no_lower", "no_digits", "no_symbols", "policy"])
+            .conflicts_with("words")
+    )
+)]
 pub struct GenPassOpts {
-    #[arg(short, long, default_value_t = 16)]
-    pub length: u8,
+    #[command(subcommand)]
+    pub cmd: Option<GenPassSubCommand>,
+
+    #[command(flatten)]
+    pub policy: PasswordPolicyOpts,
+
+    #[arg(long, default_value_t = 0, value_parser = clap::value_parser!(u8).range(0..=4), help = "regenerate until the zxcvbn score is at least this")]
+    pub min_score: u8,
+
+    #[arg(value_enum, long, num_args = 0..=1, default_missing_value = "text", conflicts_with_all = ["count", "usernames", "format"], help = "print a detailed strength report to stderr")]
+    pub report: Option<ReportFormat>,
+
+    #[arg(short, long, default_value_t = 1, value_parser = clap::value_parser!(u32).range(1..), help = "how many passwords to generate")]
+    pub count: u32,
+
+    #[arg(long, value_parser = verify_file, conflicts_with = "count", help = "one password per username in this file, one per line")]
+    pub usernames: Option<String>,
+
+    #[arg(
+        value_enum,
+        long,
+        help = "write username, password and score records instead of bare passwords"
+    )]
+    pub format: Option<OutputFormat>,
+
+    #[arg(short, long, default_value = "-", requires = "format")]
+    pub output: String,
+
+    #[arg(
+        long,
+        requires = "format",
+        help = "replace the sqlite output file if it exists"
+    )]
+    pub force: bool,
+
+    #[arg(
+        long,
+        help = "derive every password from this seed, for test fixtures only"
+    )]
+    pub seed: Option<String>,
+
+    #[arg(
+        short,
+        long,
+        help = "generate a passphrase of this many words instead of a password"
+    )]
+    pub words: Option<u8>,
 
-    #[arg(short, long, default_value_t = true)]
+    #[arg(long, default_value = "-", requires = "words")]
+    pub separator: String,
+
+    #[arg(long, requires = "words", help = "capitalize every word")]
+    pub capitalize: bool,
+
+    #[arg(long, requires = "words", help = "append a digit to a random word")]
+    pub add_digit: bool,
+
+    #[arg(long, requires = "words", help = "append a symbol to a random word")]
+    pub add_symbol: bool,
+
+    #[arg(long, value_parser = verify_file, requires = "words", help = "one word per line, the embedded EFF wordlist if omitted")]
+    pub wordlist: Option<String>,
+
+    // the class switches before policies, always on and kept so existing scripts still run
+    #[arg(short, long, hide = true)]
     pub uppercase: bool,
 
-    #[arg(long, default_value_t = true)]
+    #[arg(long, hide = true)]
     pub lowercase: bool,
 
-    #[arg(short, long, default_value_t = true)]
+    #[arg(short, long, hide = true)]
     pub number: bool,
 
-    #[arg(short, long, default_value_t = true)]
+    #[arg(short, long, hide = true)]
     pub symbol: bool,
 }
 
+#[derive(Debug, Subcommand)]
+#[enum_dispatch(CmdExector)]
+pub enum GenPassSubCommand {
+    #[command(about = "Score passwords, one per line, and flag duplicates and policy violations")]
+    Check(GenPassCheckOpts),
+}
+
+/// What generated passwords are made of, and what checked ones are held to.
+#[derive(Debug, Args)]
+pub struct PasswordPolicyOpts {
+    #[arg(short, long, help = "password length [default: 16]")]
+    pub length: Option<usize>,
+
+    #[arg(long, help = "at least this many uppercase letters [default: 1]")]
+    pub min_upper: Option<usize>,
+
+    #[arg(long, help = "at least this many lowercase letters [default: 1]")]
+    pub min_lower: Option<usize>,
+
+    #[arg(long, help = "at least this many digits [default: 1]")]
+    pub min_digits: Option<usize>,
+
+    #[arg(long, help = "at least this many symbols [default: 1]")]
+    pub min_symbols: Option<usize>,
+
+    #[arg(
+        long,
+        allow_hyphen_values = true,
+        help = "symbols to pick from [default: !@#$%^&*_]"
+    )]
+    pub symbols: Option<String>,
+
+    #[arg(
+        long,
+        allow_hyphen_values = true,
+        help = "characters never to use, eg: lI1O0"
+    )]
+    pub exclude: Option<String>,
+
+    #[arg(long, conflicts_with = "min_upper")]
+    pub no_upper: bool,
+
+    #[arg(long, conflicts_with = "min_lower")]
+    pub no_lower: bool,
+
+    #[arg(long, conflicts_with = "min_digits")]
+    pub no_digits: bool,
+
+    #[arg(long, conflicts_with_all = ["min_symbols", "symbols"])]
+    pub no_symbols: bool,
+
+    #[arg(
+        long,
+        requires = "policy_file",
+        help = "start from a named policy, the flags above override it"
+    )]
+    pub policy: Option<String>,
+
+    #[arg(long, value_parser = verify_file, requires = "policy", help = "yaml file mapping policy names to policies")]
+    pub policy_file: Option<String>,
+}
+
+#[derive(Debug, Parser)]
+pub struct GenPassCheckOpts {
+    #[arg(short, long, value_parser = verify_file, default_value = "-", help = "passwords, one per line")]
+    pub input: String,
+
+    #[arg(short, long, default_value = "-")]
+    pub output: String,
+
+    #[arg(value_enum, long, default_value_t = OutputFormat::Csv)]
+    pub format: OutputFormat,
+
+    #[arg(long, help = "replace the sqlite output file if it exists")]
+    pub force: bool,
+
+    #[arg(
+        long = "user-input",
+        value_delimiter = ',',
+        help = "words an attacker would try first, eg: usernames or the service name"
+    )]
+    pub user_inputs: Vec<String>,
+
+    #[command(flatten)]
+    pub policy: PasswordPolicyOpts,
+}
+
 impl CmdExector for GenPassOpts {
     async fn execute(self) -> anyhow::Result<()> {
-        let password = process_genpass(
-            self.length,
-            self.uppercase,
-            self.lowercase,
-            self.number,
-            self.symbol,
-        )?;
-        let score = zxcvbn(&password, &[])?;
-        println!("{}", password);
-        // 只打印到错误输出
-        eprintln!("Password strength: {}", score.score());
+        if let Some(cmd) = self.cmd {
+            return cmd.execute().await;
+        }
+        if self.uppercase || self.lowercase || self.number || self.symbol {
+            eprintln!("{}", CLASS_FLAGS_WARNING);
+        }
+        let mut rng = match &self.seed {
+            Some(seed) => {
+                eprintln!("{}", SEED_WARNING);
+                ChaCha20Rng::from_seed(*blake3::hash(seed.as_bytes()).as_bytes())
+            }
+            None => ChaCha20Rng::from_entropy(),
+        };
+        let labels = match &self.usernames {
+            Some(path) => read_usernames(path)?.into_iter().map(Some).collect(),
+            None => vec![None; self.count as usize],
+        };
+        let passphrase = match self.words {
+            Some(words) => {
+                let wordlist = match &self.wordlist {
+                    Some(path) => Wordlist::load(path)?,
+                    None => Wordlist::eff(),
+                };
+                let spec = PassphraseSpec {
+                    words: words as usize,
+                    separator: &self.separator,
+                    capitalize: self.capitalize,
+                    digit: self.add_digit,
+                    symbol: self.add_symbol,
+                };
+                Some((wordlist, spec))
+            }
+            None => None,
+        };
+        let policy = self.policy.policy()?;
+
+        let mut generated = Vec::with_capacity(labels.len());
+        for label in labels {
+            // a password containing the username it's for is easier to guess
+            let user_inputs = label.as_deref().into_iter().collect::<Vec<_>>();
+            let (password, report) = match &passphrase {
+                Some((wordlist, spec)) => {
+                    let (passphrase, mut report) =
+                        generate_scored(self.min_score, &user_inputs, || {
+                            process_genphrase(wordlist, spec, &mut rng)
+                        })?;
+                    report.entropy = Some(passphrase.entropy);
+                    (passphrase.phrase, report)
+                }
+                None => generate_scored(self.min_score, &user_inputs, || {
+                    process_genpass(&policy, &mut rng)
+                })?,
+            };
+            generated.push((label, password, report));
+        }
+
+        if let Some(format) = self.format {
+            let records = generated
+                .iter()
+                .map(|(label, password, report)| {
+                    let mut record = serde_json::Map::new();
+                    if let Some(label) = label {
+                        record.insert("username".to_string(), label.as_str().into());
+                    }
+                    record.insert("password".to_string(), password.as_str().into());
+                    record.insert("score".to_string(), report.score.into());
+                    if let Some(entropy) = report.entropy {
+                        record.insert("entropy".to_string(), entropy.into());
+                    }
+                    serde_json::Value::Object(record)
+                })
+                .collect::<Vec<_>>();
+            return write_records(&records, format, &self.output, "passwords", self.force);
+        }
+        if let [(None, password, report)] = generated.as_slice() {
+            println!("{}", password);
+            // 只打印到错误输出
+            match self.report {
+                None => match report.entropy {
+                    Some(entropy) => eprintln!(
+                        "Password strength: {}, entropy: {:.1} bits",
+                        report.score, entropy
+                    ),
+                    None => eprintln!("Password strength: {}", report.score),
+                },
+                Some(ReportFormat::Text) => eprint!("{}", render_strength(report)),
+                Some(ReportFormat::Json) => {
+                    eprintln!("{}", serde_json::to_string_pretty(report)?)
+                }
+            }
+            return Ok(());
+        }
+        for (label, password, _) in &generated {
+            match label {
+                Some(label) => println!("{}\t{}", label, password),
+                None => println!("{}", password),
+            }
+        }
+        Ok(())
+    }
+}
+
+impl CmdExector for GenPassCheckOpts {
+    async fn execute(self) -> anyhow::Result<()> {
+        let user_inputs = self
+            .user_inputs
+            .iter()
+            .map(|s| s.as_str())
+            .collect::<Vec<_>>();
+        let checks = process_genpass_check(&self.input, &user_inputs, &self.policy.policy()?)?;
+        let weak = checks.iter().filter(|c| c.score < 3).count();
+        let duplicates = checks.iter().filter(|c| c.duplicate_of.is_some()).count();
+        let violating = checks.iter().filter(|c| !c.violations.is_empty()).count();
+        let records = checks
+            .iter()
+            .map(serde_json::to_value)
+            .collect::<Result<Vec<_>, _>>()?;
+        write_records(&records, self.format, &self.output, "passwords", self.force)?;
+        eprintln!(
+            "{} passwords: {} scored below 3, {} duplicates, {} violate the policy",
+            checks.len(),
+            weak,
+            duplicates,
+            violating
+        );
         Ok(())
     }
 }
+
+fn read_usernames(path: &str) -> anyhow::Result<Vec<String>> {
+    let mut content = String::new();
+    get_reader(path)?.read_to_string(&mut content)?;
+    let usernames = content
+        .lines()
+        .map(str::trim)
+        .filter(|line| !line.is_empty())
+        .map(String::from)
+        .collect::<Vec<_>>();
+    if usernames.is_empty() {
+        anyhow::bail!("no usernames in {}", path);
+    }
+    Ok(usernames)
+}
+
+impl PasswordPolicyOpts {
+    /// The policy file's policy, or the default one, with the flags given applied on top.
+    fn policy(&self) -> anyhow::Result<PasswordPolicy> {
+        let mut policy = match (&self.policy_file, &self.policy) {
+            (Some(path), Some(name)) => PasswordPolicy::load(path, name)?,
+            _ => PasswordPolicy::default(),
+        };
+        let overrides = [
+            (self.min_upper, &mut policy.min_upper),
+            (self.min_lower, &mut policy.min_lower),
+            (self.min_digits, &mut policy.min_digits),
+            (self.min_symbols, &mut policy.min_symbols),
+            (self.length, &mut policy.length),
+        ];
+        for (flag, value) in overrides {
+            if let Some(flag) = flag {
+                *value = flag;
+            }
+        }
+        if let Some(symbols) = &self.symbols {
+            policy.symbols = symbols.clone();
+        }
+        if let Some(exclude) = &self.exclude {
+            policy.exclude.push_str(exclude);
+        }
+        let disabled = [
+            (self.no_upper, &mut policy.upper),
+            (self.no_lower, &mut policy.lower),
+            (self.no_digits, &mut policy.digits),
+            (self.no_symbols, &mut policy.symbols),
+        ];
+        for (off, chars) in disabled {
+            if off {
+                chars.clear();
+            }
+        }
+        Ok(policy)
+    }
+}
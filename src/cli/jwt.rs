@@ -1,4 +1,4 @@
-use clap::{arg, Parser, Subcommand};
+use clap::{Parser, Subcommand};
 use enum_dispatch::enum_dispatch;
 
 use crate::{process_jwt_sign, process_jwt_verify, CmdExector};
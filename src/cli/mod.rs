@@ -1,14 +1,19 @@
 use std::path::{Path, PathBuf};
 
-use clap::{command, Parser, Subcommand};
+use clap::{Parser, Subcommand};
+use encoding_rs::Encoding;
 use enum_dispatch::enum_dispatch;
 use regex::Regex;
 
-use crate::CmdExector;
+use crate::{CmdExector, TextEncoding};
 
 pub use self::base64::{Base64DecodeOpts, Base64EncodeOpts};
-pub use self::csv::CsvOpts;
-pub use self::genpass::GenPassOpts;
+pub use self::csv::{
+    CsvCatOpts, CsvConvertOpts, CsvDedupOpts, CsvDialectOpts, CsvDiffOpts, CsvJoinOpts,
+    CsvMeltOpts, CsvOpts, CsvPivotOpts, CsvProtectOpts, CsvQueryOpts, CsvShowOpts, CsvSortOpts,
+    CsvSplitOpts, CsvStatsOpts, CsvSubCommand, CsvUnprotectOpts, CsvValidateOpts,
+};
+pub use self::genpass::{GenPassCheckOpts, GenPassOpts, GenPassSubCommand, PasswordPolicyOpts};
 pub use self::http::HttpServeOpts;
 pub use self::jwt::{JwtSignOpts, JwtSubCommand, JwtVerifyOpts};
 pub use self::text::{
@@ -24,7 +29,10 @@ mod jwt;
 mod text;
 
 pub use self::base64::{Base64Format, Base64SubCommand};
-pub use self::csv::OutputFormat;
+pub use self::csv::{
+    Aggregate, DiffFormat, InputFormat, JoinKind, OutputFormat, ProtectMethod, ReportFormat,
+    SortBufferOpts,
+};
 pub use self::http::HttpSubCommand;
 pub use self::text::TextSignFormat;
 
@@ -41,12 +49,17 @@ impl CmdExector for Opts {
     }
 }
 
+// parsed once per run, so the size of the csv options doesn't matter
+#[allow(clippy::large_enum_variant)]
 #[derive(Subcommand, Debug)]
 #[enum_dispatch(CmdExector)]
 pub enum SubCommand {
     #[command(name = "csv", about = "Show CSV, or convert CSV to other formats")]
     Csv(CsvOpts),
-    #[command(name = "genpass", about = "Generate a random password")]
+    #[command(
+        name = "genpass",
+        about = "Generate a random password, or check existing ones"
+    )]
     GenPass(GenPassOpts),
     #[command(subcommand, about = "Base64 encode or decode")]
     Base64(Base64SubCommand),
@@ -66,6 +79,28 @@ fn verify_file(filename: &str) -> Result<String, &'static str> {
     }
 }
 
+fn parse_delimiter(delimiter: &str) -> Result<u8, &'static str> {
+    match delimiter {
+        "\\t" | "tab" => Ok(b'\t'),
+        _ => match delimiter.as_bytes() {
+            [c] if c.is_ascii() => Ok(*c),
+            _ => Err("delimiter must be a single ASCII character"),
+        },
+    }
+}
+
+fn parse_encoding(label: &str) -> Result<TextEncoding, &'static str> {
+    TextEncoding::for_label(label)
+        .ok_or("unknown encoding, eg: utf-8, gbk, gb18030, shift_jis, utf-16, latin1")
+}
+
+fn parse_output_encoding(label: &str) -> Result<&'static Encoding, &'static str> {
+    match parse_encoding(label)? {
+        TextEncoding::Fixed(encoding) => Ok(encoding),
+        TextEncoding::Auto => Err("output encoding can't be auto"),
+    }
+}
+
 fn verify_path(path: &str) -> Result<PathBuf, &'static str> {
     let p = Path::new(path);
     if p.exists() && p.is_dir() {
@@ -109,6 +144,30 @@ mod tests {
         assert_eq!(verify_file("not-exist"), Err("File does not exist"));
     }
 
+    #[test]
+    fn test_parse_delimiter() {
+        assert_eq!(parse_delimiter(";"), Ok(b';'));
+        assert_eq!(parse_delimiter("\t"), Ok(b'\t'));
+        assert_eq!(parse_delimiter("\\t"), Ok(b'\t'));
+        assert!(parse_delimiter("ab").is_err());
+        assert!(parse_delimiter("é").is_err());
+    }
+
+    #[test]
+    fn test_parse_encoding() {
+        assert_eq!(parse_encoding("auto"), Ok(TextEncoding::Auto));
+        assert_eq!(
+            parse_encoding("GBK"),
+            Ok(TextEncoding::Fixed(encoding_rs::GBK))
+        );
+        assert_eq!(
+            parse_output_encoding("latin1"),
+            Ok(encoding_rs::WINDOWS_1252)
+        );
+        assert!(parse_output_encoding("auto").is_err());
+        assert!(parse_encoding("klingon").is_err());
+    }
+
     #[test]
     fn test_parse_duration() {
         assert_eq!(parse_duration("10"), Ok(10));
@@ -0,0 +1,102 @@
+use anyhow::{bail, Result};
+use serde_json::{Map, Value};
+
+use super::csv_convert::{read_table, CsvReadOpts};
+
+/// Concatenate the records of several files in the column order of the first one. The files
+/// must have the same columns, in any order, unless `union` allows missing ones to be empty.
+pub fn process_csv_cat(inputs: &[String], union: bool, opts: &CsvReadOpts) -> Result<Vec<Value>> {
+    let mut columns: Vec<String> = Vec::new();
+    let mut files = Vec::new();
+    for input in inputs {
+        let (names, rows) = read_table(input, opts)?;
+
+        if files.is_empty() {
+            columns = names;
+        } else if union {
+            for name in names {
+                if !columns.contains(&name) {
+                    columns.push(name);
+                }
+            }
+        } else {
+            let extra = names
+                .iter()
+                .filter(|n| !columns.contains(n))
+                .map(|n| n.as_str())
+                .collect::<Vec<_>>();
+            let missing = columns
+                .iter()
+                .filter(|c| !names.contains(c))
+                .map(String::as_str)
+                .collect::<Vec<_>>();
+            if !extra.is_empty() || !missing.is_empty() {
+                bail!(
+                    "{} doesn't match the columns of {}: extra [{}], missing [{}], use --union to merge them",
+                    input,
+                    inputs[0],
+                    extra.join(", "),
+                    missing.join(", ")
+                );
+            }
+        }
+        files.push(rows);
+    }
+
+    Ok(files
+        .into_iter()
+        .flatten()
+        .map(|mut row| {
+            let ordered = columns
+                .iter()
+                .map(|c| (c.clone(), row.remove(c).unwrap_or(Value::Null)))
+                .collect::<Map<_, _>>();
+            Value::Object(ordered)
+        })
+        .collect())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_process_csv_cat() -> Result<()> {
+        let inputs = ["assets/juventus.csv", "fixtures/juventus-loans.csv"].map(String::from);
+        let rows = process_csv_cat(&inputs, false, &CsvReadOpts::default())?;
+        assert_eq!(rows.len(), 29);
+        let kean = rows[28].as_object().unwrap();
+        assert_eq!(
+            kean.keys().collect::<Vec<_>>(),
+            ["Name", "Position", "DOB", "Nationality", "Kit Number"]
+        );
+        assert_eq!(kean["Kit Number"], "9");
+        Ok(())
+    }
+
+    #[test]
+    fn test_cat_incompatible_columns() -> Result<()> {
+        let inputs = ["assets/juventus.csv", "fixtures/juventus-contracts.csv"].map(String::from);
+        let err = process_csv_cat(&inputs, false, &CsvReadOpts::default()).unwrap_err();
+        assert!(err.to_string().contains(
+            "extra [Contract Until, Salary], missing [Position, DOB, Nationality, Kit Number]"
+        ));
+
+        let rows = process_csv_cat(&inputs, true, &CsvReadOpts::default())?;
+        assert_eq!(rows.len(), 31);
+        assert_eq!(rows[0]["Salary"], Value::Null);
+        assert_eq!(rows[27]["Position"], Value::Null);
+        Ok(())
+    }
+
+    #[test]
+    fn test_cat_header_only_file() -> Result<()> {
+        let dir = tempfile::tempdir()?;
+        let empty = dir.path().join("empty.csv");
+        std::fs::write(&empty, "Name,Position,DOB,Nationality,Kit Number\n")?;
+        let inputs = [empty.to_str().unwrap(), "assets/juventus.csv"].map(String::from);
+        let rows = process_csv_cat(&inputs, false, &CsvReadOpts::default())?;
+        assert_eq!(rows.len(), 27);
+        Ok(())
+    }
+}
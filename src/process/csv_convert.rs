@@ -1,39 +1,774 @@
-use anyhow::Result;
-use serde::{Deserialize, Serialize};
-use std::fs;
-
-use crate::cli::OutputFormat;
-
-#[derive(Debug, Serialize, Deserialize)]
-#[serde(rename_all = "PascalCase")]
-struct Player {
-    name: String,
-    position: String,
-    #[serde(rename = "DOB")]
-    dob: String,
-    nationality: String,
-    #[serde(rename = "Kit Number")]
-    kit: u8,
-}
-
-pub fn process_csv(input: &str, output: String, format: OutputFormat) -> Result<()> {
-    let mut rdr = csv::Reader::from_path(input).unwrap();
-    let mut ret = Vec::with_capacity(128);
-    let headers = rdr.headers()?.clone();
-    for record in rdr.records() {
-        let record = record?;
-        let value = headers
-            .iter()
-            .zip(record.iter())
-            .collect::<serde_json::Value>();
-        ret.push(value);
+use anyhow::{anyhow, bail, Result};
+use encoding_rs::{Encoding, UTF_8};
+use serde::Deserialize;
+use serde_json::{Map, Value};
+use std::{
+    collections::HashSet,
+    io::{Read, Write},
+};
+
+use crate::{
+    cli::{InputFormat, OutputFormat},
+    get_reader, get_writer,
+};
+
+use super::csv_dialect::CsvDialect;
+use super::csv_encoding::EncodeWriter;
+use super::csv_markup::{render_html, render_markdown};
+use super::csv_schema::{infer_column_types, ColumnType, CsvSchema};
+use super::csv_sheet::{is_spreadsheet, open_sheet, write_xlsx};
+use super::csv_sql::{render_sql, table_name, write_sqlite, DEFAULT_TABLE};
+use super::csv_transform::RowTransform;
+
+/// Largest array index accepted in a flattened key like `tags[3]`.
+const MAX_INDEX: usize = 9999;
+
+/// Column names and the rows under them.
+type Table = (Vec<String>, Vec<Map<String, Value>>);
+
+/// How csv cells are turned into json values.
+#[derive(Debug, Default)]
+pub struct CsvReadOpts {
+    /// infer integer/float/boolean/date types per column instead of keeping strings
+    pub infer: bool,
+    /// forced column types and renames
+    pub schema: Option<CsvSchema>,
+    pub dialect: CsvDialect,
+    /// computed columns and a row filter applied to every record read
+    pub transform: RowTransform,
+}
+
+impl CsvReadOpts {
+    /// Whether date strings in the output column `column` are dates rather than text: what
+    /// the schema says, or whether types are inferred at all.
+    pub fn typed_dates(&self, column: &str) -> bool {
+        let forced = self.schema.as_ref().and_then(|schema| {
+            let declared = schema
+                .columns
+                .iter()
+                .find(|c| schema.output_name(&c.name) == column)?;
+            declared.kind
+        });
+        match forced {
+            Some(kind) => matches!(kind, ColumnType::Date | ColumnType::Datetime),
+            None => self.infer,
+        }
+    }
+}
+
+/// How records are written out.
+#[derive(Debug)]
+pub struct CsvWriteOpts {
+    pub encoding: &'static Encoding,
+    /// embed a script sorting html tables by the clicked header
+    pub sortable: bool,
+    /// rebuild nested values from dotted and indexed csv headers like `address.city`
+    pub nest: bool,
+    /// replace an existing sqlite database
+    pub force: bool,
+}
+
+impl Default for CsvWriteOpts {
+    fn default() -> Self {
+        Self {
+            encoding: UTF_8,
+            sortable: false,
+            nest: true,
+            force: false,
+        }
     }
+}
 
+pub fn process_csv(
+    input: &str,
+    input_format: InputFormat,
+    opts: &CsvReadOpts,
+    output: String,
+    format: OutputFormat,
+    write_opts: &CsvWriteOpts,
+) -> Result<()> {
+    let mut records = read_records(input, input_format, opts)?;
+    if write_opts.nest && input_format.is_tabular() && format != OutputFormat::Csv {
+        records = records
+            .iter()
+            .map(|r| r.as_object().map(unflatten).unwrap_or_else(|| r.clone()))
+            .collect();
+    }
+    let table = table_name(input);
     let res = match format {
-        OutputFormat::Json => serde_json::to_string_pretty(&ret)?,
-        OutputFormat::Yaml => serde_yaml::to_string(&ret)?,
+        OutputFormat::Sqlite => return write_sqlite(&records, &table, &output, write_opts.force),
+        OutputFormat::Xlsx => {
+            return write_xlsx(&records, &table, &output, |column| opts.typed_dates(column))
+        }
+        OutputFormat::Sql => render_sql(&records, &table)?,
+        OutputFormat::Html => render_html(&records, &table, write_opts),
+        _ => render_records(&records, format)?,
     };
-    fs::write(output, res)?;
+    let mut wtr = EncodeWriter::new(get_writer(&output)?, write_opts.encoding);
+    wtr.write_all(res.as_bytes())?;
+    wtr.finish()?;
 
     Ok(())
 }
+
+/// Read every record of `input` as a json value, whatever the source format is.
+pub fn read_records(input: &str, format: InputFormat, opts: &CsvReadOpts) -> Result<Vec<Value>> {
+    Ok(read_columns(input, format, opts)?.1)
+}
+
+/// Like [`read_records`], also returning the columns of csv and spreadsheet input: the
+/// header, renamed by the schema, and then the computed columns. Other formats have none.
+fn read_columns(
+    input: &str,
+    format: InputFormat,
+    opts: &CsvReadOpts,
+) -> Result<(Vec<String>, Vec<Value>)> {
+    let (columns, records) = if format == InputFormat::Csv {
+        read_csv(open_csv(input, &opts.dialect)?, opts)?
+    } else if format.is_tabular() {
+        read_csv(open_sheet(input, &opts.dialect)?, opts)?
+    } else {
+        let mut reader = get_reader(input)?;
+        let mut content = String::new();
+        reader.read_to_string(&mut content)?;
+        let records = match format {
+            InputFormat::Json => read_json(&content)?,
+            InputFormat::Ndjson => read_ndjson(&content)?,
+            InputFormat::Yaml => read_yaml(&content)?,
+            InputFormat::Toml => read_toml(&content)?,
+            InputFormat::Csv | InputFormat::Xlsx | InputFormat::Ods => unreachable!(),
+        };
+        return Ok((Vec::new(), opts.transform.apply_all(records)?));
+    };
+    let columns = opts.transform.output_columns(&columns)?;
+    Ok((columns, opts.transform.apply_all(records)?))
+}
+
+/// Read every record of `input` as a json object, detecting the format from its extension,
+/// along with the column names. Csv and spreadsheet columns come from the header, so a file
+/// without rows still has them, other formats list the keys in first seen order.
+pub fn read_table(input: &str, opts: &CsvReadOpts) -> Result<Table> {
+    let (mut columns, records) = read_columns(input, InputFormat::detect(input), opts)?;
+    let rows = records
+        .into_iter()
+        .map(|record| match record {
+            Value::Object(map) => Ok(map),
+            v => Err(anyhow!(
+                "{}: expected records to be objects, got {}",
+                input,
+                v
+            )),
+        })
+        .collect::<Result<Vec<_>>>()?;
+    for name in rows.iter().flat_map(|row| row.keys()) {
+        if !columns.contains(name) {
+            columns.push(name.clone());
+        }
+    }
+    Ok((columns, rows))
+}
+
+/// Write records to `output`, a path or `-` for stdout. Sql and sqlite output put them in a
+/// table named `table`, an existing sqlite database is only replaced with `force`.
+pub fn write_records(
+    records: &[Value],
+    format: OutputFormat,
+    output: &str,
+    table: &str,
+    force: bool,
+) -> Result<()> {
+    let res = match format {
+        OutputFormat::Sqlite => return write_sqlite(records, table, output, force),
+        OutputFormat::Xlsx => return write_xlsx(records, table, output, |_| true),
+        OutputFormat::Sql => render_sql(records, table)?,
+        OutputFormat::Html => render_html(records, table, &CsvWriteOpts::default()),
+        _ => render_records(records, format)?,
+    };
+    get_writer(output)?.write_all(res.as_bytes())?;
+    Ok(())
+}
+
+/// Render records into the given output format.
+pub fn render_records(records: &[Value], format: OutputFormat) -> Result<String> {
+    let res = match format {
+        OutputFormat::Json => serde_json::to_string_pretty(records)?,
+        OutputFormat::Yaml => serde_yaml::to_string(records)?,
+        OutputFormat::Toml => {
+            let records = records.iter().map(strip_nulls).collect::<Vec<_>>();
+            let mut doc = Map::new();
+            doc.insert("rows".to_string(), Value::Array(records));
+            toml::to_string_pretty(&doc).map_err(|e| {
+                anyhow!(
+                    "{}, toml has no null for empty array items, use --no-nest",
+                    e
+                )
+            })?
+        }
+        OutputFormat::Ndjson => {
+            let mut res = String::new();
+            for record in records {
+                res.push_str(&serde_json::to_string(record)?);
+                res.push('\n');
+            }
+            res
+        }
+        OutputFormat::Csv => render_csv(records)?,
+        OutputFormat::Sql => render_sql(records, DEFAULT_TABLE)?,
+        OutputFormat::Markdown => render_markdown(records),
+        OutputFormat::Html => render_html(records, DEFAULT_TABLE, &CsvWriteOpts::default()),
+        OutputFormat::Sqlite => bail!("sqlite output must be written to a file"),
+        OutputFormat::Xlsx => bail!("xlsx output must be written to a file"),
+    };
+    Ok(res)
+}
+
+/// Open `input` (a path or `-` for stdin) as a csv reader. Spreadsheets, told apart by their
+/// extension or a sheet being asked for, are read one sheet at a time.
+pub fn open_csv(input: &str, dialect: &CsvDialect) -> Result<csv::Reader<Box<dyn Read>>> {
+    Ok(open_csv_dialect(input, dialect)?.0)
+}
+
+/// Like [`open_csv`], also returning the dialect `input` is read with, to write output the
+/// same way.
+pub fn open_csv_dialect(
+    input: &str,
+    dialect: &CsvDialect,
+) -> Result<(csv::Reader<Box<dyn Read>>, CsvDialect)> {
+    if dialect.sheet.is_some() || is_spreadsheet(input) {
+        // sheets are read as plain csv
+        let sheet = CsvDialect {
+            has_headers: dialect.has_headers,
+            ..Default::default()
+        };
+        return Ok((open_sheet(input, dialect)?, sheet));
+    }
+    dialect.open(get_reader(input)?)
+}
+
+fn read_csv(
+    mut rdr: csv::Reader<impl Read>,
+    opts: &CsvReadOpts,
+) -> Result<(Vec<String>, Vec<Value>)> {
+    let headers = rdr.headers()?.clone();
+    let records = rdr.records().collect::<Result<Vec<_>, _>>()?;
+
+    let types = column_types(&headers, &records, opts);
+    let builder = RecordBuilder::new(headers, types.into_iter().map(Some).collect(), opts)?;
+    let values = records
+        .iter()
+        .map(|record| builder.build(record))
+        .collect::<Result<Vec<_>>>()?;
+    Ok((builder.names, values))
+}
+
+/// Resolve the type of every column: schema first, then inference, then plain strings.
+fn column_types(
+    headers: &csv::StringRecord,
+    records: &[csv::StringRecord],
+    opts: &CsvReadOpts,
+) -> Vec<ColumnType> {
+    let mut types = if opts.infer {
+        infer_column_types(headers.len(), records.iter().map(|r| r.iter()))
+            .into_iter()
+            .map(|t| {
+                if t == ColumnType::Null {
+                    ColumnType::String
+                } else {
+                    t
+                }
+            })
+            .collect()
+    } else {
+        vec![ColumnType::String; headers.len()]
+    };
+    if let Some(schema) = &opts.schema {
+        for (t, header) in types.iter_mut().zip(headers.iter()) {
+            if let Some(kind) = schema.column(header).and_then(|c| c.kind) {
+                *t = kind;
+            }
+        }
+    }
+    types
+}
+
+/// Turns csv records into json objects keyed by the (renamed) headers.
+pub struct RecordBuilder {
+    headers: csv::StringRecord,
+    names: Vec<String>,
+    /// `None` means the type is inferred cell by cell
+    types: Vec<Option<ColumnType>>,
+}
+
+impl RecordBuilder {
+    /// The keys of the built objects, in header order.
+    pub fn names(&self) -> &[String] {
+        &self.names
+    }
+
+    /// Fails when the schema declares a column missing from `headers`.
+    pub fn new(
+        headers: csv::StringRecord,
+        types: Vec<Option<ColumnType>>,
+        opts: &CsvReadOpts,
+    ) -> Result<Self> {
+        if let Some(schema) = &opts.schema {
+            schema.check_columns(headers.iter())?;
+        }
+        let names = headers
+            .iter()
+            .map(|h| match &opts.schema {
+                Some(schema) => schema.output_name(h).to_string(),
+                None => h.to_string(),
+            })
+            .collect();
+        Ok(Self {
+            headers,
+            names,
+            types,
+        })
+    }
+
+    /// Fails on cells past the last column, which headerless input named after the widest row
+    /// at its start can have.
+    pub fn build(&self, record: &csv::StringRecord) -> Result<Value> {
+        let line = record.position().map(|p| p.line()).unwrap_or_default();
+        if record.len() > self.names.len() {
+            bail!(
+                "line {} has {} fields, expected at most {}",
+                line,
+                record.len(),
+                self.names.len()
+            );
+        }
+        let mut value = Map::new();
+        for (col, cell) in record.iter().enumerate() {
+            let (Some(name), Some(t)) = (self.names.get(col), self.types.get(col)) else {
+                continue;
+            };
+            let t = t.unwrap_or_else(|| ColumnType::infer(cell));
+            let cell = t
+                .parse(cell)
+                .map_err(|e| anyhow!("row {}, column {:?}: {}", line, &self.headers[col], e))?;
+            value.insert(name.clone(), cell);
+        }
+        Ok(Value::Object(value))
+    }
+}
+
+fn read_json(content: &str) -> Result<Vec<Value>> {
+    match serde_json::from_str(content)? {
+        Value::Array(records) => Ok(records),
+        value => Ok(vec![value]),
+    }
+}
+
+fn read_ndjson(content: &str) -> Result<Vec<Value>> {
+    content
+        .lines()
+        .enumerate()
+        .filter(|(_, line)| !line.trim().is_empty())
+        .map(|(i, line)| {
+            serde_json::from_str(line).map_err(|e| anyhow!("invalid json at line {}: {}", i + 1, e))
+        })
+        .collect()
+}
+
+fn read_yaml(content: &str) -> Result<Vec<Value>> {
+    let mut ret = Vec::new();
+    // a yaml stream may hold several documents, each being a record or a list of records
+    for doc in serde_yaml::Deserializer::from_str(content) {
+        match Value::deserialize(doc)? {
+            Value::Array(records) => ret.extend(records),
+            Value::Null => {}
+            value => ret.push(value),
+        }
+    }
+    Ok(ret)
+}
+
+fn read_toml(content: &str) -> Result<Vec<Value>> {
+    let doc: Value = toml::from_str(content)?;
+    // records live in the first array of tables, otherwise the document is a single record
+    let records = doc.as_object().and_then(|table| {
+        table.values().find_map(|v| match v {
+            Value::Array(items) if items.iter().all(Value::is_object) => Some(items.clone()),
+            _ => None,
+        })
+    });
+    Ok(records.unwrap_or_else(|| vec![doc]))
+}
+
+fn render_csv(records: &[Value]) -> Result<String> {
+    let (headers, rows) = flatten_records(records);
+    let mut wtr = csv::Writer::from_writer(Vec::new());
+    wtr.write_record(&headers)?;
+    for row in &rows {
+        wtr.write_record(
+            headers
+                .iter()
+                .map(|key| row.get(key).map(cell_to_string).unwrap_or_default()),
+        )?;
+    }
+    Ok(String::from_utf8(wtr.into_inner()?)?)
+}
+
+/// Flatten records into rows of a table. The headers are the union of all flattened keys,
+/// in first seen order.
+pub fn flatten_records(records: &[Value]) -> (Vec<String>, Vec<Map<String, Value>>) {
+    let rows = records.iter().map(flatten).collect::<Vec<_>>();
+    let mut seen = HashSet::new();
+    let headers = rows
+        .iter()
+        .flat_map(|row| row.keys())
+        .filter(|key| seen.insert(key.as_str()))
+        .cloned()
+        .collect();
+    (headers, rows)
+}
+
+/// Flatten a (possibly nested) value into a single level map, using `a.b` for object
+/// fields and `a[0]` for array items.
+pub fn flatten(value: &Value) -> Map<String, Value> {
+    let mut ret = Map::new();
+    match value {
+        Value::Object(_) | Value::Array(_) => flatten_into("", value, &mut ret),
+        _ => {
+            ret.insert("value".to_string(), value.clone());
+        }
+    }
+    ret
+}
+
+fn flatten_into(prefix: &str, value: &Value, out: &mut Map<String, Value>) {
+    match value {
+        Value::Object(map) if !map.is_empty() => {
+            for (k, v) in map {
+                let key = if prefix.is_empty() {
+                    k.clone()
+                } else {
+                    format!("{}.{}", prefix, k)
+                };
+                flatten_into(&key, v, out);
+            }
+        }
+        Value::Array(items) if !items.is_empty() => {
+            for (i, v) in items.iter().enumerate() {
+                flatten_into(&format!("{}[{}]", prefix, i), v, out);
+            }
+        }
+        Value::Object(_) | Value::Array(_) => {
+            out.insert(prefix.to_string(), Value::Null);
+        }
+        _ => {
+            out.insert(prefix.to_string(), value.clone());
+        }
+    }
+}
+
+/// A step in a flattened key: `address.city` is two fields, `tags[0]` a field and an index.
+#[derive(Debug, PartialEq, Eq, Hash)]
+enum Segment<'a> {
+    Field(&'a str),
+    Index(usize),
+}
+
+/// Rebuild nested objects and arrays from flattened keys, the inverse of [`flatten`]. Keys
+/// that aren't valid paths, or would nest under another key's scalar value like `a.b` next
+/// to `a`, are kept as they are.
+pub fn unflatten(map: &Map<String, Value>) -> Value {
+    let paths = map.keys().map(|key| parse_path(key)).collect::<Vec<_>>();
+    let known = paths
+        .iter()
+        .flatten()
+        .map(Vec::as_slice)
+        .collect::<HashSet<_>>();
+    let mut root = Value::Object(Map::new());
+    for ((key, value), path) in map.iter().zip(&paths) {
+        let nested = path.as_ref().is_some_and(|path| {
+            !(1..path.len()).any(|i| known.contains(&path[..i]))
+                && insert_path(&mut root, path, value)
+        });
+        if !nested {
+            if let Value::Object(obj) = &mut root {
+                obj.insert(key.clone(), value.clone());
+            }
+        }
+    }
+    root
+}
+
+fn parse_path(key: &str) -> Option<Vec<Segment<'_>>> {
+    let mut path = Vec::new();
+    for part in key.split('.') {
+        let (name, mut indices) = part.split_at(part.find('[').unwrap_or(part.len()));
+        if name.is_empty() {
+            return None;
+        }
+        path.push(Segment::Field(name));
+        while let Some(rest) = indices.strip_prefix('[') {
+            let (index, rest) = rest.split_once(']')?;
+            let index = index.parse().ok().filter(|i| *i <= MAX_INDEX)?;
+            path.push(Segment::Index(index));
+            indices = rest;
+        }
+        if !indices.is_empty() {
+            return None;
+        }
+    }
+    Some(path)
+}
+
+/// Set `value` at `path`, creating missing objects and arrays on the way. Returns false,
+/// leaving `target` untouched, when the path runs into a value of another shape.
+fn insert_path(target: &mut Value, path: &[Segment], value: &Value) -> bool {
+    let Some((segment, rest)) = path.split_first() else {
+        return false;
+    };
+    let empty = || match rest.first() {
+        Some(Segment::Index(_)) => Value::Array(Vec::new()),
+        _ => Value::Object(Map::new()),
+    };
+    let slot = match (segment, target) {
+        (Segment::Field(name), Value::Object(map)) => {
+            if rest.is_empty() {
+                if map.contains_key(*name) {
+                    return false;
+                }
+                map.insert(name.to_string(), value.clone());
+                return true;
+            }
+            map.entry(name.to_string()).or_insert_with(empty)
+        }
+        (Segment::Index(index), Value::Array(items)) => {
+            if items.len() <= *index {
+                items.resize(index + 1, Value::Null);
+            }
+            if rest.is_empty() {
+                // items padded with nulls for a later index can still be filled in
+                if !items[*index].is_null() {
+                    return false;
+                }
+                items[*index] = value.clone();
+                return true;
+            }
+            if items[*index].is_null() {
+                items[*index] = empty();
+            }
+            &mut items[*index]
+        }
+        _ => return false,
+    };
+    insert_path(slot, rest, value)
+}
+
+/// Render a scalar json value as a csv cell.
+pub fn cell_to_string(value: &Value) -> String {
+    match value {
+        Value::Null => String::new(),
+        Value::String(s) => s.clone(),
+        v => v.to_string(),
+    }
+}
+
+fn strip_nulls(value: &Value) -> Value {
+    match value {
+        Value::Object(map) => Value::Object(
+            map.iter()
+                .filter(|(_, v)| !v.is_null())
+                .map(|(k, v)| (k.clone(), strip_nulls(v)))
+                .collect(),
+        ),
+        // dropping an array item would shift the ones after it
+        Value::Array(items) => Value::Array(items.iter().map(strip_nulls).collect()),
+        v => v.clone(),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use serde::Serialize;
+    use serde_json::json;
+
+    #[derive(Debug, Serialize, Deserialize)]
+    #[serde(rename_all = "PascalCase")]
+    struct Player {
+        name: String,
+        position: String,
+        #[serde(rename = "DOB")]
+        dob: String,
+        nationality: String,
+        #[serde(rename = "Kit Number")]
+        kit: u8,
+    }
+
+    #[test]
+    fn test_read_csv_records() -> Result<()> {
+        let opts = CsvReadOpts::default();
+        let records = read_records("assets/juventus.csv", InputFormat::Csv, &opts)?;
+        assert_eq!(records.len(), 27);
+        assert_eq!(records[0]["Name"], "Wojciech Szczesny");
+        assert_eq!(records[0]["Kit Number"], "1");
+        Ok(())
+    }
+
+    #[test]
+    fn test_read_empty_cells() -> Result<()> {
+        let dir = tempfile::tempdir()?;
+        let path = dir.path().join("cells.csv");
+        std::fs::write(&path, "a,b\n1,\n,x\n")?;
+        let path = path.to_str().unwrap();
+        let records = read_records(path, InputFormat::Csv, &CsvReadOpts::default())?;
+        assert_eq!(records[0], json!({"a": "1", "b": ""}));
+        let opts = CsvReadOpts {
+            infer: true,
+            ..Default::default()
+        };
+        // only string columns keep empty strings
+        let records = read_records(path, InputFormat::Csv, &opts)?;
+        assert_eq!(
+            records,
+            [json!({"a": 1, "b": ""}), json!({"a": null, "b": "x"})]
+        );
+        Ok(())
+    }
+
+    #[test]
+    fn test_headerless_row_wider_than_sample() -> Result<()> {
+        let dir = tempfile::tempdir()?;
+        let path = dir.path().join("ragged.csv");
+        std::fs::write(&path, format!("{}x,y,z\n", "a,b\n".repeat(5000)))?;
+        let opts = CsvReadOpts {
+            dialect: CsvDialect {
+                has_headers: false,
+                ..Default::default()
+            },
+            ..Default::default()
+        };
+        let err = read_records(path.to_str().unwrap(), InputFormat::Csv, &opts).unwrap_err();
+        assert_eq!(
+            err.to_string(),
+            "line 5001 has 3 fields, expected at most 2"
+        );
+        Ok(())
+    }
+
+    #[test]
+    fn test_read_csv_typed_records() -> Result<()> {
+        let opts = CsvReadOpts {
+            infer: true,
+            ..Default::default()
+        };
+        let records = read_records("assets/juventus.csv", InputFormat::Csv, &opts)?;
+        assert_eq!(records[0]["Kit Number"], 1);
+        let players: Vec<Player> = serde_json::from_value(Value::Array(records))?;
+        assert_eq!(players[1].kit, 37);
+        Ok(())
+    }
+
+    #[test]
+    fn test_read_csv_with_schema() -> Result<()> {
+        let schema: CsvSchema = serde_yaml::from_str(
+            "columns:\n  - name: Kit Number\n    type: float\n    rename: kit\n  - name: DOB\n    type: integer\n",
+        )?;
+        let opts = CsvReadOpts {
+            infer: true,
+            schema: Some(schema),
+            ..Default::default()
+        };
+        let err = read_records("assets/juventus.csv", InputFormat::Csv, &opts).unwrap_err();
+        assert!(err.to_string().starts_with("row 2, column \"DOB\""));
+
+        let mut schema = opts.schema.unwrap();
+        schema.columns.pop();
+        let opts = CsvReadOpts {
+            infer: true,
+            schema: Some(schema),
+            ..Default::default()
+        };
+        let records = read_records("assets/juventus.csv", InputFormat::Csv, &opts)?;
+        assert_eq!(records[0]["kit"], 1.0);
+        assert!(records[0].get("Kit Number").is_none());
+
+        let schema: CsvSchema =
+            serde_yaml::from_str("columns:\n  - name: Kit No\n    type: integer\n")?;
+        let opts = CsvReadOpts {
+            schema: Some(schema),
+            ..Default::default()
+        };
+        let err = read_records("assets/juventus.csv", InputFormat::Csv, &opts).unwrap_err();
+        assert_eq!(
+            err.to_string(),
+            "schema columns not found in the header: \"Kit No\""
+        );
+        Ok(())
+    }
+
+    #[test]
+    fn test_flatten_nested_value() {
+        let value = json!({"name": "a", "address": {"city": "b"}, "tags": ["x", "y"], "empty": []});
+        let flat = flatten(&value);
+        assert_eq!(
+            flat.keys().collect::<Vec<_>>(),
+            ["name", "address.city", "tags[0]", "tags[1]", "empty"]
+        );
+        assert_eq!(flat["empty"], Value::Null);
+    }
+
+    #[test]
+    fn test_round_trip_formats() -> Result<()> {
+        let records = vec![
+            json!({"a": 1, "b": "x", "c": null}),
+            json!({"a": 2, "b": "y"}),
+        ];
+        let ndjson = render_records(&records, OutputFormat::Ndjson)?;
+        assert_eq!(read_ndjson(&ndjson)?, records);
+        let yaml = render_records(&records, OutputFormat::Yaml)?;
+        assert_eq!(read_yaml(&yaml)?, records);
+        let toml = render_records(&records, OutputFormat::Toml)?;
+        assert_eq!(read_toml(&toml)?[1], records[1]);
+        // toml has no null, but dropping one from an array would move the items after it
+        let tags = [json!({"tags": [null, "b"]})];
+        assert!(render_records(&tags, OutputFormat::Toml).is_err());
+        let csv = render_records(&records, OutputFormat::Csv)?;
+        assert_eq!(csv, "a,b,c\n1,x,\n2,y,\n");
+        Ok(())
+    }
+
+    #[test]
+    fn test_unflatten() {
+        let nested = json!({
+            "name": "Ann",
+            "address": {"city": "Turin", "zip": "10100"},
+            "tags": ["a", {"k": 1}],
+            "empty": null
+        });
+        let flat = flatten(&nested);
+        assert_eq!(
+            flat.keys().collect::<Vec<_>>(),
+            [
+                "name",
+                "address.city",
+                "address.zip",
+                "tags[0]",
+                "tags[1].k",
+                "empty"
+            ]
+        );
+        assert_eq!(unflatten(&flat), nested);
+
+        // out of order indices, and keys that don't nest cleanly
+        let flat = json!({"t[1]": 2, "t[0]": 1, "No.": 3, "a": 1, "a.b": 2, "x[y]": 4});
+        assert_eq!(
+            unflatten(flat.as_object().unwrap()),
+            json!({"t": [1, 2], "No.": 3, "a": 1, "a.b": 2, "x[y]": 4})
+        );
+
+        // a scalar and a key nesting under it keep both values, whatever the header order
+        let flat = json!({"a.b": 2, "a": 1, "t[0].k": 5, "t[0]": 6});
+        assert_eq!(
+            unflatten(flat.as_object().unwrap()),
+            json!({"a.b": 2, "a": 1, "t[0].k": 5, "t": [6]})
+        );
+    }
+}
@@ -0,0 +1,268 @@
+use std::{
+    collections::HashSet,
+    io::{Cursor, Read, Write},
+};
+
+use anyhow::Result;
+
+use super::csv_encoding::TextEncoding;
+use super::csv_schema::ColumnType;
+
+const SAMPLE_SIZE: usize = 64 * 1024;
+const SAMPLE_ROWS: usize = 50;
+const DELIMITERS: &[u8] = b",;\t|:";
+
+/// How a csv file is laid out.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct CsvDialect {
+    pub delimiter: u8,
+    pub quote: u8,
+    pub has_headers: bool,
+    /// guess the fields above from the start of the file
+    pub auto: bool,
+    pub encoding: TextEncoding,
+    /// sheet to read from a spreadsheet, by name or 1-based position
+    pub sheet: Option<String>,
+}
+
+impl Default for CsvDialect {
+    fn default() -> Self {
+        Self {
+            delimiter: b',',
+            quote: b'"',
+            has_headers: true,
+            auto: false,
+            encoding: TextEncoding::default(),
+            sheet: None,
+        }
+    }
+}
+
+impl CsvDialect {
+    /// Build a csv reader decoding the input to utf-8, sniffing the dialect first in auto
+    /// mode. Headerless files get generated column names: column1, column2, ...
+    pub fn reader<R: Read + 'static>(&self, reader: R) -> Result<csv::Reader<Box<dyn Read>>> {
+        Ok(self.open(reader)?.0)
+    }
+
+    /// Like [`CsvDialect::reader`], also returning the dialect the input is read with, which
+    /// is the sniffed one in auto mode.
+    pub fn open<R: Read + 'static>(
+        &self,
+        reader: R,
+    ) -> Result<(csv::Reader<Box<dyn Read>>, CsvDialect)> {
+        let mut dialect = self.clone();
+        let mut reader = self.encoding.decode(reader)?;
+        let mut names: Option<csv::StringRecord> = None;
+        if self.auto || !self.has_headers {
+            let mut sample = Vec::with_capacity(SAMPLE_SIZE);
+            reader
+                .by_ref()
+                .take(SAMPLE_SIZE as u64)
+                .read_to_end(&mut sample)?;
+            if self.auto {
+                dialect = Self {
+                    encoding: self.encoding,
+                    sheet: self.sheet.clone(),
+                    ..Self::sniff(&sample)
+                };
+            }
+            if !dialect.has_headers {
+                // headerless rows may be ragged, so name as many columns as the widest one has
+                let width = parse_sample(&sample, dialect.delimiter, dialect.quote)
+                    .iter()
+                    .map(|row| row.len())
+                    .max()
+                    .unwrap_or_default();
+                names = Some((1..=width).map(|i| format!("column{}", i)).collect());
+            }
+            reader = Box::new(Cursor::new(sample).chain(reader));
+        }
+
+        let mut rdr = csv::ReaderBuilder::new()
+            .delimiter(dialect.delimiter)
+            .quote(dialect.quote)
+            .flexible(!dialect.has_headers)
+            .from_reader(reader);
+        // set before the first read, the first row is then read as a record
+        if let Some(names) = names {
+            rdr.set_headers(names);
+        }
+        Ok((rdr, dialect))
+    }
+
+    /// Build a csv writer laying records out the way this dialect reads them.
+    pub fn writer<W: Write>(&self, writer: W) -> csv::Writer<W> {
+        csv::WriterBuilder::new()
+            .delimiter(self.delimiter)
+            .quote(self.quote)
+            .flexible(true)
+            .from_writer(writer)
+    }
+
+    /// Guess delimiter, quote character and header presence from a sample of the file.
+    pub fn sniff(sample: &[u8]) -> Self {
+        let quote = sniff_quote(sample);
+        let mut best: Option<(usize, usize, u8)> = None;
+        for &delimiter in DELIMITERS {
+            let rows = parse_sample(sample, delimiter, quote);
+            let widths = rows.iter().map(|r| r.len()).collect::<Vec<_>>();
+            let Some(width) = mode(&widths) else {
+                continue;
+            };
+            if width < 2 {
+                continue;
+            }
+            // prefer the delimiter that splits most rows the same way, then the widest split
+            let consistent = widths.iter().filter(|w| **w == width).count();
+            if best.is_none_or(|(c, w, _)| (consistent, width) > (c, w)) {
+                best = Some((consistent, width, delimiter));
+            }
+        }
+
+        let delimiter = best.map(|(_, _, d)| d).unwrap_or(b',');
+        let rows = parse_sample(sample, delimiter, quote);
+        Self {
+            delimiter,
+            quote,
+            has_headers: sniff_header(&rows),
+            auto: false,
+            encoding: TextEncoding::default(),
+            sheet: None,
+        }
+    }
+}
+
+fn parse_sample(sample: &[u8], delimiter: u8, quote: u8) -> Vec<Vec<String>> {
+    let mut rdr = csv::ReaderBuilder::new()
+        .delimiter(delimiter)
+        .quote(quote)
+        .has_headers(false)
+        .flexible(true)
+        .from_reader(sample);
+    let mut rows = rdr
+        .records()
+        .take(SAMPLE_ROWS + 1)
+        .map_while(|r| r.ok())
+        .map(|r| r.iter().map(String::from).collect::<Vec<_>>())
+        .collect::<Vec<_>>();
+    // the sample may end in the middle of a row
+    if sample.len() >= SAMPLE_SIZE || rows.len() > SAMPLE_ROWS {
+        rows.pop();
+    }
+    rows
+}
+
+/// A quote is whatever character wraps fields right after a delimiter or line start.
+fn sniff_quote(sample: &[u8]) -> u8 {
+    let opening = |q: u8| {
+        sample
+            .windows(2)
+            .filter(|w| w[1] == q && (DELIMITERS.contains(&w[0]) || w[0] == b'\n'))
+            .count()
+            + usize::from(sample.first() == Some(&q))
+    };
+    if opening(b'\'') > opening(b'"') {
+        b'\''
+    } else {
+        b'"'
+    }
+}
+
+/// The first row is a header when, column by column, it looks different from the rows below:
+/// text above numbers, or a different length above fixed-length values.
+fn sniff_header(rows: &[Vec<String>]) -> bool {
+    let Some((first, rest)) = rows.split_first() else {
+        return true;
+    };
+    let distinct = first.iter().collect::<HashSet<_>>();
+    if first.iter().any(|c| c.trim().is_empty()) || distinct.len() != first.len() {
+        return false;
+    }
+    if rest.is_empty() {
+        return true;
+    }
+
+    let mut votes = 0i32;
+    for (col, cell) in first.iter().enumerate() {
+        let values = rest.iter().filter_map(|r| r.get(col)).collect::<Vec<_>>();
+        let kind = values
+            .iter()
+            .fold(ColumnType::Null, |t, v| t.merge(ColumnType::infer(v)));
+        match kind {
+            ColumnType::Null => {}
+            ColumnType::String => {
+                let len = values[0].chars().count();
+                if values.iter().all(|v| v.chars().count() == len) {
+                    votes += if cell.chars().count() != len { 1 } else { -1 };
+                }
+            }
+            kind => {
+                votes += if ColumnType::infer(cell) != kind {
+                    1
+                } else {
+                    -1
+                }
+            }
+        }
+    }
+    // all-text files are assumed to carry a header, as most of them do
+    votes >= 0
+}
+
+fn mode(values: &[usize]) -> Option<usize> {
+    let mut best = None;
+    for v in values {
+        let count = values.iter().filter(|x| *x == v).count();
+        if best.is_none_or(|(_, c)| count > c) {
+            best = Some((*v, count));
+        }
+    }
+    best.map(|(v, _)| v)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_sniff_dialect() {
+        let dialect = CsvDialect::sniff(b"name;city;score\nAnna;\"Berlin; DE\";1,5\nBob;Paris;2\n");
+        assert_eq!(dialect.delimiter, b';');
+        assert_eq!(dialect.quote, b'"');
+        assert!(dialect.has_headers);
+
+        let dialect = CsvDialect::sniff(b"1\t'a b'\t2.5\n2\t'c'\t3.5\n");
+        assert_eq!(dialect.delimiter, b'\t');
+        assert_eq!(dialect.quote, b'\'');
+        assert!(!dialect.has_headers);
+
+        let dialect = CsvDialect::sniff(std::fs::read("assets/juventus.csv").unwrap().as_slice());
+        assert_eq!(dialect, CsvDialect::default());
+    }
+
+    #[test]
+    fn test_headerless_reader() -> Result<()> {
+        let dialect = CsvDialect {
+            has_headers: false,
+            delimiter: b'|',
+            ..Default::default()
+        };
+        let mut rdr = dialect.reader(Cursor::new("a|1\nb|2\n"))?;
+        assert_eq!(rdr.headers()?, vec!["column1", "column2"]);
+        let records = rdr.records().collect::<Result<Vec<_>, _>>()?;
+        assert_eq!(records.len(), 2);
+        assert_eq!(&records[0][0], "a");
+        assert_eq!(records[0].position().map(|p| p.line()), Some(1));
+
+        // ragged rows are read as they are, named after the widest one
+        let mut rdr = dialect.reader(Cursor::new("a|1\nb|2|x\nc\n"))?;
+        assert_eq!(rdr.headers()?, vec!["column1", "column2", "column3"]);
+        let widths = rdr
+            .records()
+            .map(|r| r.map(|r| r.len()))
+            .collect::<Result<Vec<_>, _>>()?;
+        assert_eq!(widths, [2, 3, 1]);
+        Ok(())
+    }
+}
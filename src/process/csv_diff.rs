@@ -0,0 +1,395 @@
+use std::{collections::HashMap, fmt::Write};
+
+use anyhow::{anyhow, bail, Result};
+use serde::Serialize;
+use serde_json::{Map, Value};
+
+use super::csv_convert::open_csv;
+use super::csv_dialect::CsvDialect;
+
+/// Everything that changed between two versions of a csv file, rows matched by key.
+#[derive(Debug, Serialize)]
+pub struct CsvDiff {
+    pub key: Vec<String>,
+    pub added_columns: Vec<String>,
+    pub removed_columns: Vec<String>,
+    pub added: Vec<DiffRow>,
+    pub removed: Vec<DiffRow>,
+    pub modified: Vec<ModifiedRow>,
+    pub unchanged: usize,
+}
+
+#[derive(Debug, Serialize)]
+pub struct DiffRow {
+    pub key: String,
+    /// line number in the file the row comes from
+    pub row: u64,
+    pub values: Map<String, Value>,
+}
+
+#[derive(Debug, Serialize)]
+pub struct ModifiedRow {
+    pub key: String,
+    pub old_row: u64,
+    pub new_row: u64,
+    pub changes: Vec<CellChange>,
+    #[serde(skip)]
+    before: Vec<String>,
+    #[serde(skip)]
+    after: Vec<String>,
+}
+
+#[derive(Debug, PartialEq, Serialize)]
+pub struct CellChange {
+    pub column: String,
+    pub old: String,
+    pub new: String,
+}
+
+/// A csv file loaded in memory, rows indexed by their key.
+struct Table {
+    headers: Vec<String>,
+    /// rows as (key cells, line, cells)
+    rows: Vec<(Vec<String>, u64, Vec<String>)>,
+    index: HashMap<Vec<String>, usize>,
+}
+
+impl Table {
+    fn load(input: &str, dialect: &CsvDialect, key: &[String]) -> Result<Self> {
+        let mut rdr = open_csv(input, dialect)?;
+        let headers = rdr.headers()?.iter().map(String::from).collect::<Vec<_>>();
+        let key_columns = key
+            .iter()
+            .map(|k| {
+                headers
+                    .iter()
+                    .position(|h| h == k)
+                    .ok_or_else(|| anyhow!("key column {:?} not found in {}", k, input))
+            })
+            .collect::<Result<Vec<_>>>()?;
+
+        let mut rows = Vec::new();
+        let mut index = HashMap::new();
+        for record in rdr.records() {
+            let record = record?;
+            let line = record.position().map(|p| p.line()).unwrap_or_default();
+            let cells = record.iter().map(String::from).collect::<Vec<_>>();
+            let key = key_columns
+                .iter()
+                .map(|i| cells.get(*i).cloned().unwrap_or_default())
+                .collect::<Vec<_>>();
+            if let Some(first) = index.insert(key.clone(), rows.len()) {
+                let (_, first_line, _) = &rows[first];
+                bail!(
+                    "duplicate key {:?} in {} at rows {} and {}",
+                    display_key(&key),
+                    input,
+                    first_line,
+                    line
+                );
+            }
+            rows.push((key, line, cells));
+        }
+        Ok(Self {
+            headers,
+            rows,
+            index,
+        })
+    }
+
+    fn row(&self, key: &[String], line: u64, cells: &[String]) -> DiffRow {
+        DiffRow {
+            key: display_key(key),
+            row: line,
+            values: self
+                .headers
+                .iter()
+                .zip(cells)
+                .map(|(h, c)| (h.clone(), Value::String(c.clone())))
+                .collect(),
+        }
+    }
+}
+
+/// How a key reads in the diff, its cells joined by commas. Rows are matched on the cells
+/// themselves, so keys like ("a, b", "c") and ("a", "b, c") stay apart.
+fn display_key(key: &[String]) -> String {
+    key.join(", ")
+}
+
+/// Compare two csv files row by row, matching rows on the `key` columns rather than by
+/// position. Cells are compared as text, columns by name, so reordering changes nothing.
+pub fn process_csv_diff(
+    old: &str,
+    new: &str,
+    dialect: &CsvDialect,
+    key: &[String],
+) -> Result<CsvDiff> {
+    let old_table = Table::load(old, dialect, key)?;
+    let new_table = Table::load(new, dialect, key)?;
+    let missing = |from: &Table, to: &Table| {
+        from.headers
+            .iter()
+            .filter(|h| !to.headers.contains(h))
+            .cloned()
+            .collect::<Vec<_>>()
+    };
+    // columns present in both files, as (old index, new index)
+    let shared = old_table
+        .headers
+        .iter()
+        .enumerate()
+        .filter_map(|(i, h)| Some((i, new_table.headers.iter().position(|n| n == h)?)))
+        .collect::<Vec<_>>();
+
+    let mut diff = CsvDiff {
+        key: key.to_vec(),
+        added_columns: missing(&new_table, &old_table),
+        removed_columns: missing(&old_table, &new_table),
+        added: Vec::new(),
+        removed: Vec::new(),
+        modified: Vec::new(),
+        unchanged: 0,
+    };
+    for (key, old_line, before) in &old_table.rows {
+        let Some(index) = new_table.index.get(key) else {
+            diff.removed.push(old_table.row(key, *old_line, before));
+            continue;
+        };
+        let (_, new_line, after) = &new_table.rows[*index];
+        let changes = shared
+            .iter()
+            .filter_map(|(o, n)| {
+                let old = before.get(*o).map(String::as_str).unwrap_or_default();
+                let new = after.get(*n).map(String::as_str).unwrap_or_default();
+                (old != new).then(|| CellChange {
+                    column: old_table.headers[*o].clone(),
+                    old: old.to_string(),
+                    new: new.to_string(),
+                })
+            })
+            .collect::<Vec<_>>();
+        if changes.is_empty() {
+            diff.unchanged += 1;
+        } else {
+            diff.modified.push(ModifiedRow {
+                key: display_key(key),
+                old_row: *old_line,
+                new_row: *new_line,
+                changes,
+                before: before.clone(),
+                after: after.clone(),
+            });
+        }
+    }
+    for (key, new_line, after) in &new_table.rows {
+        if !old_table.index.contains_key(key) {
+            diff.added.push(new_table.row(key, *new_line, after));
+        }
+    }
+    Ok(diff)
+}
+
+/// Render a diff for people: one block per changed row with its before/after cells.
+pub fn render_diff(diff: &CsvDiff) -> String {
+    let mut out = String::new();
+    if !diff.added_columns.is_empty() {
+        writeln!(out, "columns added: {}", diff.added_columns.join(", ")).unwrap();
+    }
+    if !diff.removed_columns.is_empty() {
+        writeln!(out, "columns removed: {}", diff.removed_columns.join(", ")).unwrap();
+    }
+    for row in &diff.removed {
+        writeln!(out, "- removed {:?} (row {})", row.key, row.row).unwrap();
+    }
+    for row in &diff.added {
+        writeln!(out, "+ added {:?} (row {})", row.key, row.row).unwrap();
+        for (column, value) in &row.values {
+            writeln!(
+                out,
+                "    {}: {}",
+                column,
+                value.as_str().unwrap_or_default()
+            )
+            .unwrap();
+        }
+    }
+    for row in &diff.modified {
+        writeln!(
+            out,
+            "~ modified {:?} (row {} -> {})",
+            row.key, row.old_row, row.new_row
+        )
+        .unwrap();
+        for change in &row.changes {
+            writeln!(
+                out,
+                "    {}: {:?} -> {:?}",
+                change.column, change.old, change.new
+            )
+            .unwrap();
+        }
+    }
+    writeln!(
+        out,
+        "{} added, {} removed, {} modified, {} unchanged",
+        diff.added.len(),
+        diff.removed.len(),
+        diff.modified.len(),
+        diff.unchanged
+    )
+    .unwrap();
+    out
+}
+
+/// Render a diff in the shape of a unified patch, one hunk per changed row, ordered as in the
+/// old file with additions last.
+pub fn render_patch(diff: &CsvDiff, old: &str, new: &str) -> Result<String> {
+    let mut hunks = diff
+        .removed
+        .iter()
+        .map(|r| {
+            let cells = r.values.values().map(cell).collect::<Vec<_>>();
+            Ok((
+                r.row,
+                format!("@@ -{} +0 @@ {}\n-{}", r.row, r.key, line(&cells)?),
+            ))
+        })
+        .chain(diff.modified.iter().map(|r| {
+            Ok((
+                r.old_row,
+                format!(
+                    "@@ -{} +{} @@ {}\n-{}\n+{}",
+                    r.old_row,
+                    r.new_row,
+                    r.key,
+                    line(&r.before)?,
+                    line(&r.after)?
+                ),
+            ))
+        }))
+        .collect::<Result<Vec<_>>>()?;
+    hunks.sort_by_key(|(row, _)| *row);
+
+    let mut out = format!("--- {}\n+++ {}\n", old, new);
+    if !diff.added_columns.is_empty() || !diff.removed_columns.is_empty() {
+        writeln!(out, "@@ header @@").unwrap();
+        for column in &diff.removed_columns {
+            writeln!(out, "-{}", column).unwrap();
+        }
+        for column in &diff.added_columns {
+            writeln!(out, "+{}", column).unwrap();
+        }
+    }
+    for (_, hunk) in hunks {
+        writeln!(out, "{}", hunk).unwrap();
+    }
+    for row in &diff.added {
+        let cells = row.values.values().map(cell).collect::<Vec<_>>();
+        writeln!(out, "@@ -0 +{} @@ {}\n+{}", row.row, row.key, line(&cells)?).unwrap();
+    }
+    Ok(out)
+}
+
+fn cell(value: &Value) -> String {
+    value.as_str().unwrap_or_default().to_string()
+}
+
+/// A row written back as a csv line, quoted where needed.
+fn line(cells: &[String]) -> Result<String> {
+    let mut wtr = csv::WriterBuilder::new()
+        .terminator(csv::Terminator::Any(b'\n'))
+        .from_writer(Vec::new());
+    wtr.write_record(cells)?;
+    let bytes = wtr.into_inner()?;
+    Ok(String::from_utf8(bytes)?.trim_end_matches('\n').to_string())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_process_csv_diff() -> Result<()> {
+        let key = vec!["Name".to_string()];
+        let diff = process_csv_diff(
+            "assets/juventus.csv",
+            "fixtures/juventus-next.csv",
+            &CsvDialect::default(),
+            &key,
+        )?;
+        assert_eq!(diff.removed.len(), 1);
+        assert_eq!(diff.removed[0].key, "Mario Mandzukic");
+        assert_eq!(diff.added[0].key, "Dejan Kulusevski");
+        assert_eq!(diff.added[0].values["Nationality"], "Sweden");
+        assert_eq!(diff.modified.len(), 2);
+        assert_eq!(
+            diff.modified[1].changes,
+            [
+                CellChange {
+                    column: "DOB".into(),
+                    old: "Jan 28, 1978 (41)".into(),
+                    new: "Jan 28, 1978 (42)".into(),
+                },
+                CellChange {
+                    column: "Kit Number".into(),
+                    old: "77".into(),
+                    new: "1".into(),
+                }
+            ]
+        );
+        assert_eq!(diff.unchanged, 24);
+
+        let patch = render_patch(&diff, "old.csv", "new.csv")?;
+        assert!(patch.starts_with("--- old.csv\n+++ new.csv\n@@ -3 +3 @@ Mattia Perin\n"));
+        assert!(patch.contains("+Mattia Perin,Goalkeeper,\"Nov 10, 1992 (26)\",Italy,22\n"));
+        assert!(patch.ends_with("@@ -0 +28 @@ Dejan Kulusevski\n+Dejan Kulusevski,Right Winger,\"Apr 25, 2000 (19)\",Sweden,44\n"));
+        Ok(())
+    }
+
+    #[test]
+    fn test_diff_key_errors() {
+        let key = vec!["Position".to_string()];
+        let err = process_csv_diff(
+            "assets/juventus.csv",
+            "assets/juventus.csv",
+            &CsvDialect::default(),
+            &key,
+        )
+        .unwrap_err();
+        assert_eq!(
+            err.to_string(),
+            "duplicate key \"Goalkeeper\" in assets/juventus.csv at rows 2 and 3"
+        );
+
+        let key = vec!["Id".to_string()];
+        assert!(process_csv_diff(
+            "assets/juventus.csv",
+            "assets/juventus.csv",
+            &CsvDialect::default(),
+            &key,
+        )
+        .is_err());
+    }
+
+    #[test]
+    fn test_diff_composite_keys() -> Result<()> {
+        let dir = tempfile::tempdir()?;
+        let old = dir.path().join("old.csv");
+        let new = dir.path().join("new.csv");
+        std::fs::write(&old, "a,b,v\n\"x, y\",z,1\nx,\"y, z\",2\n")?;
+        std::fs::write(&new, "a,b,v\nx,\"y, z\",2\n\"x, y\",z,3\n")?;
+        let key = vec!["a".to_string(), "b".to_string()];
+        let diff = process_csv_diff(
+            old.to_str().unwrap(),
+            new.to_str().unwrap(),
+            &CsvDialect::default(),
+            &key,
+        )?;
+        assert_eq!(diff.unchanged, 1);
+        assert_eq!(diff.modified.len(), 1);
+        assert_eq!(diff.modified[0].old_row, 2);
+        assert_eq!(diff.modified[0].changes[0].new, "3");
+        Ok(())
+    }
+}
@@ -0,0 +1,384 @@
+use std::io::{self, Cursor, Read, Write};
+
+use encoding_rs::{
+    Decoder, DecoderResult, Encoder, EncoderResult, Encoding, GB18030, SHIFT_JIS, UTF_16BE,
+    UTF_16LE, UTF_8, WINDOWS_1252,
+};
+
+const SAMPLE_SIZE: usize = 64 * 1024;
+const BUF_SIZE: usize = 8 * 1024;
+/// Scored when guessing the encoding of a file without a BOM, the first one wins a tie. The
+/// multi-byte ones reject most text in the others, windows-1252 (a superset of Latin-1)
+/// accepts anything.
+const CANDIDATES: [&Encoding; 4] = [UTF_8, GB18030, SHIFT_JIS, WINDOWS_1252];
+/// How much worse a malformed byte sequence is than an unlikely character.
+const MALFORMED_PENALTY: usize = 100;
+
+/// The character encoding of a text input.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum TextEncoding {
+    /// check for a BOM, then guess from the first bytes
+    Auto,
+    Fixed(&'static Encoding),
+}
+
+impl Default for TextEncoding {
+    fn default() -> Self {
+        Self::Fixed(UTF_8)
+    }
+}
+
+impl TextEncoding {
+    /// Look up an encoding by one of its WHATWG labels, eg: gbk, shift_jis, utf-16, latin1.
+    pub fn for_label(label: &str) -> Option<Self> {
+        if label.eq_ignore_ascii_case("auto") {
+            return Some(Self::Auto);
+        }
+        Encoding::for_label(label.as_bytes()).map(Self::Fixed)
+    }
+
+    /// Wrap `reader` so it yields utf-8. A BOM always wins over the configured encoding.
+    pub fn decode<R: Read + 'static>(&self, mut reader: R) -> io::Result<Box<dyn Read>> {
+        match self {
+            Self::Fixed(encoding) => Ok(Box::new(DecodeReader::new(reader, encoding))),
+            Self::Auto => {
+                let mut sample = Vec::with_capacity(SAMPLE_SIZE);
+                reader
+                    .by_ref()
+                    .take(SAMPLE_SIZE as u64)
+                    .read_to_end(&mut sample)?;
+                let encoding = detect(&sample);
+                let reader = Cursor::new(sample).chain(reader);
+                Ok(Box::new(DecodeReader::new(reader, encoding)))
+            }
+        }
+    }
+}
+
+/// Guess the encoding of a sample: its BOM if it has one, otherwise the candidate decoding it
+/// into the most plausible text.
+pub fn detect(sample: &[u8]) -> &'static Encoding {
+    if let Some((encoding, _)) = Encoding::for_bom(sample) {
+        return encoding;
+    }
+    CANDIDATES
+        .into_iter()
+        .min_by_key(|encoding| implausibility(encoding, sample))
+        .unwrap_or(WINDOWS_1252)
+}
+
+/// How unlikely `sample` is to be text in `encoding`. Malformed sequences weigh the most,
+/// then every character rarely seen in real text: control codes, private use characters,
+/// half-width katakana, and for GB18030 the hanzi outside the common GB2312 set, which is
+/// what Shift_JIS kanji read as GB18030 turn into.
+fn implausibility(encoding: &'static Encoding, sample: &[u8]) -> usize {
+    let mut decoder = encoding.new_decoder_without_bom_handling();
+    let capacity = decoder
+        .max_utf8_buffer_length(sample.len())
+        .unwrap_or(SAMPLE_SIZE * 3 + 16);
+    let mut text = String::with_capacity(capacity);
+    // the sample may end in the middle of a character, which isn't an error
+    let _ = decoder.decode_to_string(sample, &mut text, false);
+    text.chars()
+        .map(|c| match c {
+            '\u{FFFD}' => MALFORMED_PENALTY,
+            '\t' | '\r' | '\n' => 0,
+            '\u{E000}'..='\u{F8FF}' | '\u{FF61}'..='\u{FF9F}' => 1,
+            c if c.is_control() => 1,
+            c if encoding == GB18030 && !c.is_ascii() && !is_gb2312(c) => 1,
+            _ => 0,
+        })
+        .sum()
+}
+
+/// Whether `c` is in GB2312, the characters GB18030 encodes with two bytes from 0xA1 up.
+fn is_gb2312(c: char) -> bool {
+    let mut buf = [0; 4];
+    let (bytes, _, _) = GB18030.encode(c.encode_utf8(&mut buf));
+    matches!(*bytes, [lead, trail] if lead >= 0xA1 && trail >= 0xA1)
+}
+
+/// Decodes a byte stream into utf-8, failing on malformed input with its byte offset and line
+/// in the file. Lines count newlines, so they run ahead of csv rows once a quoted field spans
+/// several lines.
+struct DecodeReader<R> {
+    inner: R,
+    decoder: Decoder,
+    encoding: &'static Encoding,
+    input: Box<[u8]>,
+    start: usize,
+    end: usize,
+    output: Box<[u8]>,
+    pos: usize,
+    len: usize,
+    /// input bytes decoded so far
+    offset: u64,
+    /// newlines decoded so far
+    lines: u64,
+    eof: bool,
+    finished: bool,
+}
+
+impl<R: Read> DecodeReader<R> {
+    fn new(inner: R, encoding: &'static Encoding) -> Self {
+        Self {
+            inner,
+            decoder: encoding.new_decoder(),
+            encoding,
+            input: vec![0; BUF_SIZE].into_boxed_slice(),
+            start: 0,
+            end: 0,
+            output: vec![0; BUF_SIZE * 3 + 16].into_boxed_slice(),
+            pos: 0,
+            len: 0,
+            offset: 0,
+            lines: 0,
+            eof: false,
+            finished: false,
+        }
+    }
+
+    /// Decode the next chunk into `output`, returning false at the end of the input.
+    fn fill(&mut self) -> io::Result<bool> {
+        while self.pos == self.len {
+            if self.finished {
+                return Ok(false);
+            }
+            if self.start == self.end && !self.eof {
+                self.start = 0;
+                self.end = self.inner.read(&mut self.input)?;
+                self.eof = self.end == 0;
+            }
+            let (result, read, written) = self.decoder.decode_to_utf8_without_replacement(
+                &self.input[self.start..self.end],
+                &mut self.output,
+                self.eof,
+            );
+            self.start += read;
+            self.offset += read as u64;
+            self.pos = 0;
+            self.len = written;
+            self.lines += count_lines(&self.output[..written]);
+            match result {
+                DecoderResult::Malformed(bad, after) => {
+                    let at = self.offset - u64::from(bad) - u64::from(after);
+                    return Err(io::Error::new(
+                        io::ErrorKind::InvalidData,
+                        format!(
+                            "invalid {} byte sequence at byte offset {}, line {} of the file",
+                            self.encoding.name(),
+                            at,
+                            self.lines + 1
+                        ),
+                    ));
+                }
+                DecoderResult::InputEmpty if self.eof => self.finished = true,
+                _ => {}
+            }
+        }
+        Ok(true)
+    }
+}
+
+impl<R: Read> Read for DecodeReader<R> {
+    fn read(&mut self, buf: &mut [u8]) -> io::Result<usize> {
+        if !self.fill()? {
+            return Ok(0);
+        }
+        let n = buf.len().min(self.len - self.pos);
+        buf[..n].copy_from_slice(&self.output[self.pos..self.pos + n]);
+        self.pos += n;
+        Ok(n)
+    }
+}
+
+fn count_lines(bytes: &[u8]) -> u64 {
+    bytes.iter().filter(|b| **b == b'\n').count() as u64
+}
+
+/// Encodes the utf-8 written to it into another encoding, failing on characters the target
+/// can't represent. UTF-16 output starts with a BOM.
+pub struct EncodeWriter<W: Write> {
+    inner: W,
+    target: Target,
+    /// the start of a character split across writes
+    pending: Vec<u8>,
+    out: Vec<u8>,
+}
+
+enum Target {
+    Utf8,
+    Utf16 { big_endian: bool, bom: bool },
+    Other(Box<Encoder>),
+}
+
+impl<W: Write> EncodeWriter<W> {
+    pub fn new(inner: W, encoding: &'static Encoding) -> Self {
+        let target = if encoding == UTF_8 {
+            Target::Utf8
+        } else if encoding == UTF_16LE || encoding == UTF_16BE {
+            Target::Utf16 {
+                big_endian: encoding == UTF_16BE,
+                bom: true,
+            }
+        } else {
+            Target::Other(Box::new(encoding.new_encoder()))
+        };
+        Self {
+            inner,
+            target,
+            pending: Vec::new(),
+            out: vec![0; BUF_SIZE],
+        }
+    }
+
+    /// Write out whatever a stateful encoder still holds and return the inner writer. Fails
+    /// if the output ends in the middle of a character.
+    pub fn finish(mut self) -> io::Result<W> {
+        if !self.pending.is_empty() {
+            return Err(io::Error::new(
+                io::ErrorKind::InvalidData,
+                "output ends in the middle of a character",
+            ));
+        }
+        if let Target::Other(encoder) = &mut self.target {
+            let (_, _, written) =
+                encoder.encode_from_utf8_without_replacement("", &mut self.out, true);
+            self.inner.write_all(&self.out[..written])?;
+        }
+        self.inner.flush()?;
+        Ok(self.inner)
+    }
+
+    fn encode(&mut self, text: &str) -> io::Result<()> {
+        match &mut self.target {
+            Target::Utf8 => self.inner.write_all(text.as_bytes()),
+            Target::Utf16 { big_endian, bom } => {
+                let mut bytes = Vec::with_capacity(text.len() * 2 + 2);
+                let units = std::mem::take(bom).then_some(0xFEFF).into_iter();
+                for unit in units.chain(text.encode_utf16()) {
+                    bytes.extend(match big_endian {
+                        true => unit.to_be_bytes(),
+                        false => unit.to_le_bytes(),
+                    });
+                }
+                self.inner.write_all(&bytes)
+            }
+            Target::Other(encoder) => {
+                let mut text = text;
+                loop {
+                    let (result, read, written) =
+                        encoder.encode_from_utf8_without_replacement(text, &mut self.out, false);
+                    self.inner.write_all(&self.out[..written])?;
+                    text = &text[read..];
+                    match result {
+                        EncoderResult::InputEmpty => return Ok(()),
+                        EncoderResult::OutputFull => {}
+                        EncoderResult::Unmappable(c) => {
+                            return Err(io::Error::new(
+                                io::ErrorKind::InvalidData,
+                                format!(
+                                    "{:?} can't be represented in {}",
+                                    c,
+                                    encoder.encoding().name()
+                                ),
+                            ))
+                        }
+                    }
+                }
+            }
+        }
+    }
+}
+
+impl<W: Write> Write for EncodeWriter<W> {
+    fn write(&mut self, buf: &[u8]) -> io::Result<usize> {
+        self.pending.extend_from_slice(buf);
+        let pending = std::mem::take(&mut self.pending);
+        let valid = match std::str::from_utf8(&pending) {
+            Ok(text) => text.len(),
+            // an incomplete character at the end waits for the next write
+            Err(e) if e.error_len().is_none() => e.valid_up_to(),
+            Err(e) => return Err(io::Error::new(io::ErrorKind::InvalidData, e)),
+        };
+        // checked just above
+        let text = std::str::from_utf8(&pending[..valid]).expect("valid utf-8");
+        self.encode(text)?;
+        self.pending = pending[valid..].to_vec();
+        Ok(buf.len())
+    }
+
+    /// Flushes what is encoded so far, the start of a split character waits for the rest.
+    fn flush(&mut self) -> io::Result<()> {
+        self.inner.flush()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn decode(bytes: &[u8], encoding: TextEncoding) -> io::Result<String> {
+        let mut out = String::new();
+        encoding
+            .decode(Cursor::new(bytes.to_vec()))?
+            .read_to_string(&mut out)?;
+        Ok(out)
+    }
+
+    fn encode(text: &str, encoding: &'static Encoding) -> io::Result<Vec<u8>> {
+        let mut wtr = EncodeWriter::new(Vec::new(), encoding);
+        // split inside a multi-byte character on purpose
+        let (a, b) = text.as_bytes().split_at(text.len() / 2 + 1);
+        wtr.write_all(a)?;
+        // a buffered writer may flush between the halves
+        wtr.flush()?;
+        wtr.write_all(b)?;
+        wtr.finish()
+    }
+
+    #[test]
+    fn test_round_trip_encodings() -> io::Result<()> {
+        let text = "名前,都市\n张三,北京\n";
+        for label in ["gbk", "gb18030", "utf-16le", "utf-16be", "utf-8"] {
+            let encoding = Encoding::for_label(label.as_bytes()).unwrap();
+            let bytes = encode(text, encoding)?;
+            assert_eq!(decode(&bytes, TextEncoding::Fixed(encoding))?, text);
+            assert_eq!(decode(&bytes, TextEncoding::Auto)?, text, "{}", label);
+        }
+
+        let text = "名前,都市\n山田,東京\n";
+        let bytes = encode(text, SHIFT_JIS)?;
+        assert_eq!(decode(&bytes, TextEncoding::Fixed(SHIFT_JIS))?, text);
+        // these bytes are valid GB18030 too, but decode into rare hanzi like 柤慜
+        assert_eq!(detect(&bytes), SHIFT_JIS);
+        assert_eq!(decode(&bytes, TextEncoding::Auto)?, text);
+
+        let bytes = b"name,city\nJos\xe9,S\xe3o Paulo\n";
+        assert_eq!(detect(bytes), WINDOWS_1252);
+        assert_eq!(
+            decode(bytes, TextEncoding::for_label("latin1").unwrap())?,
+            "name,city\nJosé,São Paulo\n"
+        );
+        Ok(())
+    }
+
+    #[test]
+    fn test_decode_errors() {
+        let err = decode(b"a,b\n1,2\n3,\xff\n", TextEncoding::default()).unwrap_err();
+        assert_eq!(
+            err.to_string(),
+            "invalid UTF-8 byte sequence at byte offset 10, line 3 of the file"
+        );
+
+        let err = encode("a,é\n", SHIFT_JIS).unwrap_err();
+        assert_eq!(err.to_string(), "'é' can't be represented in Shift_JIS");
+
+        let mut wtr = EncodeWriter::new(Vec::new(), UTF_8);
+        wtr.write_all(&"é".as_bytes()[..1]).unwrap();
+        assert_eq!(
+            wtr.finish().unwrap_err().to_string(),
+            "output ends in the middle of a character"
+        );
+    }
+}
@@ -0,0 +1,844 @@
+use std::{cell::RefCell, cmp::Ordering, collections::HashMap, fmt};
+
+use anyhow::{anyhow, bail, Result};
+use chrono::{Datelike, NaiveDate};
+use regex::Regex;
+use serde_json::{Map, Value};
+
+use super::csv_schema::{parse_date, parse_datetime};
+
+/// Compiled patterns kept around for reuse across rows.
+const REGEX_CACHE_SIZE: usize = 64;
+/// Human readable date formats accepted by the date functions besides iso 8601, eg: the
+/// `Apr 18, 1990 (29)` of a date of birth followed by an age.
+const DATE_FORMATS: [&str; 4] = ["%b %d, %Y", "%B %d, %Y", "%d %b %Y", "%Y/%m/%d"];
+
+thread_local! {
+    static REGEX_CACHE: RefCell<HashMap<String, Regex>> = RefCell::new(HashMap::new());
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub enum Token {
+    Ident(String),
+    /// `"Kit Number"` or `` `Kit Number` ``
+    QuotedIdent(String),
+    Str(String),
+    Number(String),
+    Symbol(&'static str),
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub enum Expr {
+    Column(String),
+    Literal(Value),
+    /// `*`, only valid as `count(*)`
+    Wildcard,
+    Neg(Box<Expr>),
+    Not(Box<Expr>),
+    Binary(Box<Expr>, BinOp, Box<Expr>),
+    IsNull(Box<Expr>, bool),
+    Like(Box<Expr>, Box<Expr>, bool),
+    In(Box<Expr>, Vec<Expr>, bool),
+    Call(String, Vec<Expr>),
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum BinOp {
+    Or,
+    And,
+    Eq,
+    NotEq,
+    Lt,
+    LtEq,
+    Gt,
+    GtEq,
+    Add,
+    Sub,
+    Mul,
+    Div,
+}
+
+const SYMBOLS: &[&str] = &[
+    "<=", ">=", "!=", "<>", "==", "(", ")", ",", "*", "+", "-", "/", "=", "<", ">",
+];
+
+/// Split an expression into tokens.
+pub fn tokenize(s: &str) -> Result<Vec<Token>> {
+    let chars = s.chars().collect::<Vec<_>>();
+    let mut tokens = Vec::new();
+    let mut i = 0;
+    while i < chars.len() {
+        let c = chars[i];
+        if c.is_whitespace() {
+            i += 1;
+        } else if c == '\'' || c == '"' || c == '`' {
+            // quotes are escaped by doubling them, as in sql
+            let mut text = String::new();
+            i += 1;
+            loop {
+                match chars.get(i) {
+                    None => bail!("unterminated quote {} in {:?}", c, s),
+                    Some(&q) if q == c && chars.get(i + 1) == Some(&c) => {
+                        text.push(c);
+                        i += 2;
+                    }
+                    Some(&q) if q == c => {
+                        i += 1;
+                        break;
+                    }
+                    Some(&q) => {
+                        text.push(q);
+                        i += 1;
+                    }
+                }
+            }
+            tokens.push(if c == '\'' {
+                Token::Str(text)
+            } else {
+                Token::QuotedIdent(text)
+            });
+        } else if c.is_ascii_digit()
+            || (c == '.' && chars.get(i + 1).is_some_and(char::is_ascii_digit))
+        {
+            let start = i;
+            while i < chars.len() && (chars[i].is_ascii_digit() || chars[i] == '.') {
+                i += 1;
+            }
+            tokens.push(Token::Number(chars[start..i].iter().collect()));
+        } else if c.is_alphanumeric() || c == '_' {
+            let start = i;
+            while i < chars.len()
+                && (chars[i].is_alphanumeric() || chars[i] == '_' || chars[i] == '.')
+            {
+                i += 1;
+            }
+            tokens.push(Token::Ident(chars[start..i].iter().collect()));
+        } else {
+            let rest = chars[i..].iter().take(2).collect::<String>();
+            let sym = SYMBOLS
+                .iter()
+                .find(|sym| rest.starts_with(**sym))
+                .ok_or_else(|| anyhow!("unexpected character {:?} in {:?}", c, s))?;
+            tokens.push(Token::Symbol(sym));
+            i += sym.len();
+        }
+    }
+    Ok(tokens)
+}
+
+/// Parse a complete expression such as `Nationality = 'Italy' and "Kit Number" > 10`. Bare
+/// words following each other are read as one of `columns` when they spell its name, so
+/// `Kit Number > 10` needs no quotes.
+pub fn parse_expr(s: &str, columns: &[String]) -> Result<Expr> {
+    let mut parser = Parser::new(s)?;
+    parser.columns = columns.to_vec();
+    let expr = parser.parse_expr()?;
+    if !parser.is_done() {
+        bail!("unexpected {} in {:?}", parser.describe_next(), s);
+    }
+    Ok(expr)
+}
+
+/// A recursive descent parser over tokens, shared by filters and queries.
+pub struct Parser {
+    tokens: Vec<Token>,
+    pos: usize,
+    /// known column names that may span several bare words
+    columns: Vec<String>,
+}
+
+impl Parser {
+    pub fn new(s: &str) -> Result<Self> {
+        Ok(Self {
+            tokens: tokenize(s)?,
+            pos: 0,
+            columns: Vec::new(),
+        })
+    }
+
+    pub fn peek(&self) -> Option<&Token> {
+        self.tokens.get(self.pos)
+    }
+
+    pub fn next(&mut self) -> Option<Token> {
+        let token = self.tokens.get(self.pos).cloned();
+        self.pos += 1;
+        token
+    }
+
+    pub fn is_done(&self) -> bool {
+        self.pos >= self.tokens.len()
+    }
+
+    /// Whether the next token is the given (case insensitive) keyword.
+    pub fn peek_keyword(&self, keyword: &str) -> bool {
+        matches!(self.peek(), Some(Token::Ident(s)) if s.eq_ignore_ascii_case(keyword))
+    }
+
+    pub fn eat_keyword(&mut self, keyword: &str) -> bool {
+        let found = self.peek_keyword(keyword);
+        if found {
+            self.pos += 1;
+        }
+        found
+    }
+
+    pub fn expect_keyword(&mut self, keyword: &str) -> Result<()> {
+        if self.eat_keyword(keyword) {
+            Ok(())
+        } else {
+            bail!("expected {}, found {}", keyword, self.describe_next())
+        }
+    }
+
+    pub fn eat_symbol(&mut self, symbol: &str) -> bool {
+        let found = matches!(self.peek(), Some(Token::Symbol(s)) if *s == symbol);
+        if found {
+            self.pos += 1;
+        }
+        found
+    }
+
+    pub fn expect_symbol(&mut self, symbol: &str) -> Result<()> {
+        if self.eat_symbol(symbol) {
+            Ok(())
+        } else {
+            bail!("expected {:?}, found {}", symbol, self.describe_next())
+        }
+    }
+
+    /// A bare or quoted identifier.
+    pub fn parse_ident(&mut self) -> Result<String> {
+        match self.next() {
+            Some(Token::Ident(s)) | Some(Token::QuotedIdent(s)) => Ok(s),
+            _ => {
+                self.pos -= 1;
+                bail!("expected a name, found {}", self.describe_next())
+            }
+        }
+    }
+
+    pub fn parse_number(&mut self) -> Result<usize> {
+        match self.next() {
+            Some(Token::Number(n)) => Ok(n.parse()?),
+            _ => {
+                self.pos -= 1;
+                bail!("expected a number, found {}", self.describe_next())
+            }
+        }
+    }
+
+    pub fn describe_next(&self) -> String {
+        match self.peek() {
+            None => "end of input".to_string(),
+            Some(Token::Ident(s)) => s.clone(),
+            Some(Token::QuotedIdent(s)) => format!("{:?}", s),
+            Some(Token::Str(s)) => format!("'{}'", s),
+            Some(Token::Number(s)) => s.clone(),
+            Some(Token::Symbol(s)) => s.to_string(),
+        }
+    }
+
+    pub fn parse_expr(&mut self) -> Result<Expr> {
+        let mut left = self.parse_and()?;
+        while self.eat_keyword("or") {
+            left = Expr::Binary(Box::new(left), BinOp::Or, Box::new(self.parse_and()?));
+        }
+        Ok(left)
+    }
+
+    fn parse_and(&mut self) -> Result<Expr> {
+        let mut left = self.parse_not()?;
+        while self.eat_keyword("and") {
+            left = Expr::Binary(Box::new(left), BinOp::And, Box::new(self.parse_not()?));
+        }
+        Ok(left)
+    }
+
+    fn parse_not(&mut self) -> Result<Expr> {
+        if self.eat_keyword("not") {
+            return Ok(Expr::Not(Box::new(self.parse_not()?)));
+        }
+        self.parse_comparison()
+    }
+
+    fn parse_comparison(&mut self) -> Result<Expr> {
+        let left = self.parse_additive()?;
+        if self.eat_keyword("is") {
+            let negated = self.eat_keyword("not");
+            self.expect_keyword("null")?;
+            return Ok(Expr::IsNull(Box::new(left), negated));
+        }
+        let negated = self.eat_keyword("not");
+        if self.eat_keyword("like") {
+            let pattern = self.parse_additive()?;
+            return Ok(Expr::Like(Box::new(left), Box::new(pattern), negated));
+        }
+        if self.eat_keyword("in") {
+            self.expect_symbol("(")?;
+            let items = self.parse_list()?;
+            return Ok(Expr::In(Box::new(left), items, negated));
+        }
+        if negated {
+            bail!(
+                "expected LIKE or IN after NOT, found {}",
+                self.describe_next()
+            );
+        }
+
+        let op = match self.peek() {
+            Some(Token::Symbol("=")) | Some(Token::Symbol("==")) => BinOp::Eq,
+            Some(Token::Symbol("!=")) | Some(Token::Symbol("<>")) => BinOp::NotEq,
+            Some(Token::Symbol("<")) => BinOp::Lt,
+            Some(Token::Symbol("<=")) => BinOp::LtEq,
+            Some(Token::Symbol(">")) => BinOp::Gt,
+            Some(Token::Symbol(">=")) => BinOp::GtEq,
+            _ => return Ok(left),
+        };
+        self.pos += 1;
+        let right = self.parse_additive()?;
+        Ok(Expr::Binary(Box::new(left), op, Box::new(right)))
+    }
+
+    fn parse_additive(&mut self) -> Result<Expr> {
+        let mut left = self.parse_multiplicative()?;
+        loop {
+            let op = if self.eat_symbol("+") {
+                BinOp::Add
+            } else if self.eat_symbol("-") {
+                BinOp::Sub
+            } else {
+                return Ok(left);
+            };
+            left = Expr::Binary(Box::new(left), op, Box::new(self.parse_multiplicative()?));
+        }
+    }
+
+    fn parse_multiplicative(&mut self) -> Result<Expr> {
+        let mut left = self.parse_unary()?;
+        loop {
+            let op = if self.eat_symbol("*") {
+                BinOp::Mul
+            } else if self.eat_symbol("/") {
+                BinOp::Div
+            } else {
+                return Ok(left);
+            };
+            left = Expr::Binary(Box::new(left), op, Box::new(self.parse_unary()?));
+        }
+    }
+
+    fn parse_unary(&mut self) -> Result<Expr> {
+        if self.eat_symbol("-") {
+            return Ok(Expr::Neg(Box::new(self.parse_unary()?)));
+        }
+        self.parse_primary()
+    }
+
+    fn parse_primary(&mut self) -> Result<Expr> {
+        let token = self
+            .next()
+            .ok_or_else(|| anyhow!("unexpected end of expression"))?;
+        let expr = match token {
+            Token::Number(n) => Expr::Literal(parse_number_literal(&n)?),
+            Token::Str(s) => Expr::Literal(Value::String(s)),
+            Token::QuotedIdent(s) => Expr::Column(s),
+            Token::Symbol("(") => {
+                let expr = self.parse_expr()?;
+                self.expect_symbol(")")?;
+                expr
+            }
+            Token::Symbol("*") => Expr::Wildcard,
+            Token::Ident(s) if s.eq_ignore_ascii_case("null") => Expr::Literal(Value::Null),
+            Token::Ident(s) if s.eq_ignore_ascii_case("true") => Expr::Literal(Value::Bool(true)),
+            Token::Ident(s) if s.eq_ignore_ascii_case("false") => Expr::Literal(Value::Bool(false)),
+            Token::Ident(s) => {
+                if self.eat_symbol("(") {
+                    let args = self.parse_list()?;
+                    Expr::Call(s.to_ascii_lowercase(), args)
+                } else {
+                    Expr::Column(self.extend_column(s))
+                }
+            }
+            Token::Symbol(s) => bail!("unexpected {:?}", s),
+        };
+        Ok(expr)
+    }
+
+    /// Join the bare words after `first` into the longest known column name they spell.
+    fn extend_column(&mut self, first: String) -> String {
+        let mut name = first.clone();
+        let mut longest = (first, self.pos);
+        let mut pos = self.pos;
+        while let Some(Token::Ident(word) | Token::Number(word)) = self.tokens.get(pos) {
+            name = format!("{} {}", name, word);
+            pos += 1;
+            if self.columns.contains(&name) {
+                longest = (name.clone(), pos);
+            }
+        }
+        self.pos = longest.1;
+        longest.0
+    }
+
+    /// A comma separated list of expressions, after the opening parenthesis.
+    fn parse_list(&mut self) -> Result<Vec<Expr>> {
+        let mut items = Vec::new();
+        if self.eat_symbol(")") {
+            return Ok(items);
+        }
+        loop {
+            items.push(self.parse_expr()?);
+            if self.eat_symbol(")") {
+                return Ok(items);
+            }
+            self.expect_symbol(",")?;
+        }
+    }
+}
+
+fn parse_number_literal(n: &str) -> Result<Value> {
+    if let Ok(i) = n.parse::<i64>() {
+        return Ok(Value::from(i));
+    }
+    let f = n
+        .parse::<f64>()
+        .map_err(|_| anyhow!("invalid number {}", n))?;
+    Ok(Value::from(f))
+}
+
+pub fn is_aggregate(name: &str) -> bool {
+    matches!(name, "count" | "sum" | "avg" | "min" | "max")
+}
+
+impl Expr {
+    /// Whether an aggregate function appears anywhere in the expression.
+    pub fn has_aggregate(&self) -> bool {
+        match self {
+            Expr::Call(name, args) => is_aggregate(name) || args.iter().any(Expr::has_aggregate),
+            Expr::Neg(e) | Expr::Not(e) | Expr::IsNull(e, _) => e.has_aggregate(),
+            Expr::Binary(l, _, r) | Expr::Like(l, r, _) => l.has_aggregate() || r.has_aggregate(),
+            Expr::In(e, items, _) => e.has_aggregate() || items.iter().any(Expr::has_aggregate),
+            Expr::Column(_) | Expr::Literal(_) | Expr::Wildcard => false,
+        }
+    }
+
+    /// Evaluate the expression against a single row.
+    pub fn eval(&self, row: &Map<String, Value>) -> Result<Value> {
+        let value = match self {
+            Expr::Column(name) => row
+                .get(name)
+                .cloned()
+                .ok_or_else(|| anyhow!("unknown column {:?}", name))?,
+            Expr::Literal(v) => v.clone(),
+            Expr::Wildcard => bail!("* is only allowed in count(*)"),
+            Expr::Neg(e) => match e.eval(row)? {
+                Value::Null => Value::Null,
+                v => arithmetic(&Value::from(0), BinOp::Sub, &v)?,
+            },
+            Expr::Not(e) => Value::Bool(!truthy(&e.eval(row)?)),
+            Expr::Binary(l, BinOp::And, r) => {
+                Value::Bool(truthy(&l.eval(row)?) && truthy(&r.eval(row)?))
+            }
+            Expr::Binary(l, BinOp::Or, r) => {
+                Value::Bool(truthy(&l.eval(row)?) || truthy(&r.eval(row)?))
+            }
+            Expr::Binary(l, op, r) => binary(&l.eval(row)?, *op, &r.eval(row)?)?,
+            Expr::IsNull(e, negated) => Value::Bool(e.eval(row)?.is_null() != *negated),
+            Expr::Like(e, pattern, negated) => {
+                let (v, pattern) = (e.eval(row)?, pattern.eval(row)?);
+                if v.is_null() || pattern.is_null() {
+                    Value::Bool(false)
+                } else {
+                    let re = like_regex(&to_text(&pattern))?;
+                    Value::Bool(re.is_match(&to_text(&v)) != *negated)
+                }
+            }
+            Expr::In(e, items, negated) => {
+                let v = e.eval(row)?;
+                let mut found = false;
+                for item in items {
+                    if compare(&v, &item.eval(row)?) == Some(Ordering::Equal) {
+                        found = true;
+                        break;
+                    }
+                }
+                Value::Bool(found != *negated)
+            }
+            Expr::Call(name, _) if is_aggregate(name) => {
+                bail!("aggregate {}() is not allowed here", name)
+            }
+            Expr::Call(name, args) => {
+                let args = args
+                    .iter()
+                    .map(|a| a.eval(row))
+                    .collect::<Result<Vec<_>>>()?;
+                call(name, &args)?
+            }
+        };
+        Ok(value)
+    }
+}
+
+fn call(name: &str, args: &[Value]) -> Result<Value> {
+    let arg = |i: usize| {
+        args.get(i)
+            .ok_or_else(|| anyhow!("{}() expects at least {} argument(s)", name, i + 1))
+    };
+    let value = match name {
+        "upper" => map_text(arg(0)?, |s| s.to_uppercase()),
+        "lower" => map_text(arg(0)?, |s| s.to_lowercase()),
+        "length" => match arg(0)? {
+            Value::Null => Value::Null,
+            v => Value::from(to_text(v).chars().count()),
+        },
+        "abs" => match to_number(arg(0)?) {
+            Some(n) => number(n.abs()),
+            None => Value::Null,
+        },
+        "round" => {
+            let digits = args.get(1).and_then(to_number).unwrap_or(0.0) as i32;
+            let factor = 10f64.powi(digits);
+            match to_number(arg(0)?) {
+                Some(n) => number((n * factor).round() / factor),
+                None => Value::Null,
+            }
+        }
+        "coalesce" => args
+            .iter()
+            .find(|v| !v.is_null())
+            .cloned()
+            .unwrap_or(Value::Null),
+        "trim" => map_text(arg(0)?, |s| s.trim().to_string()),
+        "replace" => {
+            let (from, to) = (to_text(arg(1)?), to_text(arg(2)?));
+            map_text(arg(0)?, |s| s.replace(&from, &to))
+        }
+        "regex_replace" => {
+            let re = cached_regex(&to_text(arg(1)?))?;
+            let replacement = to_text(arg(2)?);
+            map_text(arg(0)?, |s| {
+                re.replace_all(s, replacement.as_str()).into_owned()
+            })
+        }
+        "split" => match arg(0)? {
+            Value::Null => Value::Null,
+            v => {
+                let text = to_text(v);
+                let parts = text.split(to_text(arg(1)?).as_str()).collect::<Vec<_>>();
+                match args.get(2).and_then(to_number) {
+                    // negative indexes count from the end
+                    Some(i) if i < 0.0 => parts.len().checked_sub(i.abs() as usize),
+                    Some(i) => Some(i as usize),
+                    None => return Ok(Value::from(parts)),
+                }
+                .and_then(|i| parts.get(i))
+                .map(|part| Value::from(*part))
+                .unwrap_or(Value::Null)
+            }
+        },
+        "year" => to_date(arg(0)?).map_or(Value::Null, |d| Value::from(d.year())),
+        "month" => to_date(arg(0)?).map_or(Value::Null, |d| Value::from(d.month())),
+        "day" => to_date(arg(0)?).map_or(Value::Null, |d| Value::from(d.day())),
+        _ => bail!("unknown function {}()", name),
+    };
+    Ok(value)
+}
+
+/// The date a value holds, ignoring anything after a human readable date.
+fn to_date(v: &Value) -> Option<NaiveDate> {
+    let s = v.as_str()?.trim();
+    parse_date(s)
+        .or_else(|| parse_datetime(s).map(|dt| dt.date()))
+        .or_else(|| {
+            DATE_FORMATS.iter().find_map(|format| {
+                let (date, rest) = NaiveDate::parse_and_remainder(s, format).ok()?;
+                (rest.is_empty() || rest.starts_with(char::is_whitespace)).then_some(date)
+            })
+        })
+}
+
+fn cached_regex(pattern: &str) -> Result<Regex> {
+    REGEX_CACHE.with(|cache| {
+        let mut cache = cache.borrow_mut();
+        if let Some(re) = cache.get(pattern) {
+            return Ok(re.clone());
+        }
+        let re = Regex::new(pattern)?;
+        if cache.len() >= REGEX_CACHE_SIZE {
+            cache.clear();
+        }
+        cache.insert(pattern.to_string(), re.clone());
+        Ok(re)
+    })
+}
+
+fn map_text(v: &Value, f: impl Fn(&str) -> String) -> Value {
+    match v {
+        Value::Null => Value::Null,
+        v => Value::String(f(&to_text(v))),
+    }
+}
+
+fn binary(l: &Value, op: BinOp, r: &Value) -> Result<Value> {
+    let value = match op {
+        BinOp::Eq => Value::Bool(compare(l, r) == Some(Ordering::Equal)),
+        BinOp::NotEq => Value::Bool(matches!(
+            compare(l, r),
+            Some(Ordering::Less) | Some(Ordering::Greater)
+        )),
+        BinOp::Lt => Value::Bool(compare(l, r) == Some(Ordering::Less)),
+        BinOp::LtEq => Value::Bool(matches!(
+            compare(l, r),
+            Some(Ordering::Less) | Some(Ordering::Equal)
+        )),
+        BinOp::Gt => Value::Bool(compare(l, r) == Some(Ordering::Greater)),
+        BinOp::GtEq => Value::Bool(matches!(
+            compare(l, r),
+            Some(Ordering::Greater) | Some(Ordering::Equal)
+        )),
+        _ => arithmetic(l, op, r)?,
+    };
+    Ok(value)
+}
+
+fn arithmetic(l: &Value, op: BinOp, r: &Value) -> Result<Value> {
+    if l.is_null() || r.is_null() {
+        return Ok(Value::Null);
+    }
+    if op == BinOp::Add
+        && (l.is_string() || r.is_string())
+        && (to_number(l).is_none() || to_number(r).is_none())
+    {
+        return Ok(Value::String(format!("{}{}", to_text(l), to_text(r))));
+    }
+    let (Some(a), Some(b)) = (to_number(l), to_number(r)) else {
+        bail!("can't apply {:?} to {} and {}", op, l, r);
+    };
+    // keep integer results integral as long as they fit
+    if let (Some(x), Some(y)) = (l.as_i64(), r.as_i64()) {
+        let res = match op {
+            BinOp::Add => x.checked_add(y),
+            BinOp::Sub => x.checked_sub(y),
+            BinOp::Mul => x.checked_mul(y),
+            _ => None,
+        };
+        if let Some(res) = res {
+            return Ok(Value::from(res));
+        }
+    }
+    let res = match op {
+        BinOp::Add => a + b,
+        BinOp::Sub => a - b,
+        BinOp::Mul => a * b,
+        BinOp::Div if b == 0.0 => return Ok(Value::Null),
+        BinOp::Div => a / b,
+        _ => unreachable!(),
+    };
+    Ok(number(res))
+}
+
+/// Compare two values the sql way: nulls are never comparable, and strings that hold
+/// numbers compare numerically against numbers.
+pub fn compare(l: &Value, r: &Value) -> Option<Ordering> {
+    match (l, r) {
+        (Value::Null, _) | (_, Value::Null) => None,
+        (Value::String(a), Value::String(b)) => Some(a.cmp(b)),
+        (Value::Bool(a), Value::Bool(b)) => Some(a.cmp(b)),
+        _ => to_number(l)?.partial_cmp(&to_number(r)?),
+    }
+}
+
+/// Order values for sorting: nulls first, then numbers, then everything else as text.
+pub fn sort_order(l: &Value, r: &Value) -> Ordering {
+    match (l, r) {
+        (Value::Null, Value::Null) => Ordering::Equal,
+        (Value::Null, _) => Ordering::Less,
+        (_, Value::Null) => Ordering::Greater,
+        _ => compare(l, r).unwrap_or_else(|| to_text(l).cmp(&to_text(r))),
+    }
+}
+
+pub fn truthy(v: &Value) -> bool {
+    match v {
+        Value::Null => false,
+        Value::Bool(b) => *b,
+        Value::Number(n) => n.as_f64().is_some_and(|n| n != 0.0),
+        Value::String(s) => !s.is_empty(),
+        Value::Array(a) => !a.is_empty(),
+        Value::Object(o) => !o.is_empty(),
+    }
+}
+
+pub fn to_number(v: &Value) -> Option<f64> {
+    match v {
+        Value::Number(n) => n.as_f64(),
+        Value::String(s) => s.trim().parse().ok(),
+        Value::Bool(b) => Some(if *b { 1.0 } else { 0.0 }),
+        _ => None,
+    }
+}
+
+pub fn to_text(v: &Value) -> String {
+    match v {
+        Value::Null => String::new(),
+        Value::String(s) => s.clone(),
+        v => v.to_string(),
+    }
+}
+
+/// A json number, integral when the float has no fraction.
+pub fn number(n: f64) -> Value {
+    if n.fract() == 0.0 && n.abs() < i64::MAX as f64 {
+        Value::from(n as i64)
+    } else {
+        serde_json::Number::from_f64(n)
+            .map(Value::Number)
+            .unwrap_or(Value::Null)
+    }
+}
+
+fn like_regex(pattern: &str) -> Result<Regex> {
+    let mut re = String::from("^");
+    for c in pattern.chars() {
+        match c {
+            '%' => re.push_str(".*"),
+            '_' => re.push('.'),
+            c => re.push_str(&regex::escape(&c.to_string())),
+        }
+    }
+    re.push('$');
+    Ok(Regex::new(&re)?)
+}
+
+impl fmt::Display for Expr {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Expr::Column(name) => write!(f, "{}", name),
+            Expr::Literal(Value::String(s)) => write!(f, "'{}'", s.replace('\'', "''")),
+            Expr::Literal(v) => write!(f, "{}", v),
+            Expr::Wildcard => write!(f, "*"),
+            Expr::Neg(e) => write!(f, "-{}", e),
+            Expr::Not(e) => write!(f, "NOT {}", e),
+            Expr::Binary(l, op, r) => write!(f, "{} {} {}", l, op, r),
+            Expr::IsNull(e, false) => write!(f, "{} IS NULL", e),
+            Expr::IsNull(e, true) => write!(f, "{} IS NOT NULL", e),
+            Expr::Like(e, p, negated) => {
+                write!(f, "{} {}LIKE {}", e, if *negated { "NOT " } else { "" }, p)
+            }
+            Expr::In(e, items, negated) => {
+                let items = items.iter().map(|i| i.to_string()).collect::<Vec<_>>();
+                let not = if *negated { "NOT " } else { "" };
+                write!(f, "{} {}IN ({})", e, not, items.join(", "))
+            }
+            Expr::Call(name, args) => {
+                let args = args.iter().map(|a| a.to_string()).collect::<Vec<_>>();
+                write!(f, "{}({})", name, args.join(", "))
+            }
+        }
+    }
+}
+
+impl fmt::Display for BinOp {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let op = match self {
+            BinOp::Or => "OR",
+            BinOp::And => "AND",
+            BinOp::Eq => "=",
+            BinOp::NotEq => "!=",
+            BinOp::Lt => "<",
+            BinOp::LtEq => "<=",
+            BinOp::Gt => ">",
+            BinOp::GtEq => ">=",
+            BinOp::Add => "+",
+            BinOp::Sub => "-",
+            BinOp::Mul => "*",
+            BinOp::Div => "/",
+        };
+        write!(f, "{}", op)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use serde_json::json;
+
+    fn row() -> Map<String, Value> {
+        json!({"Name": "Paulo Dybala", "Kit Number": 10, "Nationality": "Argentina", "Note": null})
+            .as_object()
+            .unwrap()
+            .clone()
+    }
+
+    fn eval(s: &str) -> Result<Value> {
+        let columns = row().keys().cloned().collect::<Vec<_>>();
+        parse_expr(s, &columns)?.eval(&row())
+    }
+
+    #[test]
+    fn test_tokenize() -> Result<()> {
+        let tokens = tokenize(r#""Kit Number" >= 10 and Name <> 'O''Neil'"#)?;
+        assert_eq!(
+            tokens,
+            [
+                Token::QuotedIdent("Kit Number".into()),
+                Token::Symbol(">="),
+                Token::Number("10".into()),
+                Token::Ident("and".into()),
+                Token::Ident("Name".into()),
+                Token::Symbol("<>"),
+                Token::Str("O'Neil".into()),
+            ]
+        );
+        assert!(tokenize("'open").is_err());
+        Ok(())
+    }
+
+    #[test]
+    fn test_eval_expressions() -> Result<()> {
+        assert_eq!(eval(r#""Kit Number" * 2 + 1"#)?, json!(21));
+        assert_eq!(eval(r#""Kit Number" / 4"#)?, json!(2.5));
+        assert_eq!(
+            eval("Nationality = 'Argentina' AND NOT Note IS NOT NULL")?,
+            json!(true)
+        );
+        assert_eq!(eval("Name LIKE 'Paulo%'")?, json!(true));
+        assert_eq!(eval(r#""Kit Number" IN (1, 10, 11)"#)?, json!(true));
+        assert_eq!(eval("upper(Nationality)")?, json!("ARGENTINA"));
+        assert_eq!(eval("coalesce(Note, 'none')")?, json!("none"));
+        assert_eq!(eval("Note > 1")?, json!(false));
+        assert!(eval("missing = 1").is_err());
+        assert!(parse_expr("1 +", &[]).is_err());
+        assert!(parse_expr("a b", &[]).is_err());
+        Ok(())
+    }
+
+    #[test]
+    fn test_eval_functions() -> Result<()> {
+        assert_eq!(eval("Kit Number > 5 and Kit Number < 11")?, json!(true));
+        assert_eq!(eval("Kit Number * 2")?, json!(20));
+        assert_eq!(eval("trim('  a b ')")?, json!("a b"));
+        assert_eq!(
+            eval(r"regex_replace(Name, '(\w+) (\w+)', '$2, $1')")?,
+            json!("Dybala, Paulo")
+        );
+        assert_eq!(eval("replace(Name, 'Paulo ', '')")?, json!("Dybala"));
+        assert_eq!(eval("split(Name, ' ')")?, json!(["Paulo", "Dybala"]));
+        assert_eq!(eval("split(Name, ' ', -1)")?, json!("Dybala"));
+        assert_eq!(eval("split(Name, ' ', 2)")?, json!(null));
+        assert_eq!(eval("2024 - year('Nov 15, 1993 (25)')")?, json!(31));
+        assert_eq!(eval("month('2019-07-04T10:00:00Z')")?, json!(7));
+        assert_eq!(eval("day(Note)")?, json!(null));
+        assert!(eval("regex_replace(Name, '(', '')").is_err());
+        Ok(())
+    }
+
+    #[test]
+    fn test_display_round_trip() -> Result<()> {
+        let expr = parse_expr("count(*) > 1 or Name not like 'A%'", &[])?;
+        assert_eq!(expr.to_string(), "count(*) > 1 OR Name NOT LIKE 'A%'");
+        assert!(expr.has_aggregate());
+        Ok(())
+    }
+}
@@ -0,0 +1,193 @@
+use std::collections::HashMap;
+
+use anyhow::{bail, Result};
+use serde_json::{Map, Value};
+
+use crate::cli::JoinKind;
+
+use super::csv_convert::{cell_to_string, read_table, CsvReadOpts};
+
+/// Join the records of two files on the `on` columns, which both must have. Other columns
+/// that exist on both sides are kept twice, the right one suffixed with `_right`. Rows with
+/// an empty key cell match nothing, and numbers match whatever their type, so 1.0 joins 1.
+pub fn process_csv_join(
+    left: &str,
+    right: &str,
+    on: &[String],
+    kind: JoinKind,
+    opts: &CsvReadOpts,
+) -> Result<Vec<Value>> {
+    let (left_columns, left_rows) = read_table(left, opts)?;
+    let (right_columns, right_rows) = read_table(right, opts)?;
+    check_columns(&left_columns, left, on)?;
+    check_columns(&right_columns, right, on)?;
+    let right_columns = right_columns
+        .into_iter()
+        .filter(|c| !on.contains(c))
+        .collect::<Vec<_>>();
+
+    let key = |row: &Map<String, Value>| {
+        on.iter()
+            .map(|c| row.get(c).and_then(key_cell))
+            .collect::<Option<Vec<_>>>()
+    };
+    let mut index: HashMap<Vec<String>, Vec<usize>> = HashMap::new();
+    for (i, row) in right_rows.iter().enumerate() {
+        if let Some(key) = key(row) {
+            index.entry(key).or_default().push(i);
+        }
+    }
+
+    let joined = |left: Option<&Map<String, Value>>, right: Option<&Map<String, Value>>| {
+        let mut row = Map::new();
+        for column in &left_columns {
+            let value = match (left, right) {
+                (Some(l), _) => l.get(column),
+                // unmatched right rows still carry the key columns
+                (None, Some(r)) if on.contains(column) => r.get(column),
+                _ => None,
+            };
+            row.insert(column.clone(), value.cloned().unwrap_or(Value::Null));
+        }
+        for column in &right_columns {
+            let name = if left_columns.contains(column) {
+                format!("{}_right", column)
+            } else {
+                column.clone()
+            };
+            let value = right.and_then(|r| r.get(column)).cloned();
+            row.insert(name, value.unwrap_or(Value::Null));
+        }
+        Value::Object(row)
+    };
+
+    let mut rows = Vec::new();
+    let mut matched = vec![false; right_rows.len()];
+    for l in &left_rows {
+        match key(l).and_then(|key| index.get(&key)) {
+            Some(found) => {
+                for i in found {
+                    matched[*i] = true;
+                    rows.push(joined(Some(l), Some(&right_rows[*i])));
+                }
+            }
+            None if kind != JoinKind::Inner => rows.push(joined(Some(l), None)),
+            None => {}
+        }
+    }
+    if kind == JoinKind::Full {
+        for (r, _) in right_rows.iter().zip(matched).filter(|(_, m)| !m) {
+            rows.push(joined(None, Some(r)));
+        }
+    }
+    Ok(rows)
+}
+
+/// Check the join columns are among the columns of `input`.
+fn check_columns(columns: &[String], input: &str, on: &[String]) -> Result<()> {
+    if let Some(missing) = on.iter().find(|c| !columns.contains(c)) {
+        bail!("join column {:?} not found in {}", missing, input);
+    }
+    Ok(())
+}
+
+/// A key cell as text to match on, `None` when it's null or empty. Whole numbers are written
+/// without a fraction, so an integer and a float of the same value match.
+fn key_cell(value: &Value) -> Option<String> {
+    let cell = match value {
+        Value::Number(n) => match (n.as_i64(), n.as_f64()) {
+            (Some(i), _) => i.to_string(),
+            (None, Some(f)) if f.fract() == 0.0 && f.abs() < 1e15 => format!("{}", f as i64),
+            _ => n.to_string(),
+        },
+        v => cell_to_string(v),
+    };
+    (!cell.is_empty()).then_some(cell)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn join(kind: JoinKind) -> Result<Vec<Value>> {
+        process_csv_join(
+            "assets/juventus.csv",
+            "fixtures/juventus-contracts.csv",
+            &["Name".to_string()],
+            kind,
+            &CsvReadOpts {
+                infer: true,
+                ..Default::default()
+            },
+        )
+    }
+
+    #[test]
+    fn test_process_csv_join() -> Result<()> {
+        let inner = join(JoinKind::Inner)?;
+        assert_eq!(inner.len(), 3);
+        assert_eq!(inner[0]["Name"], "Gianluigi Buffon");
+        assert_eq!(inner[0]["Kit Number"], 77);
+        assert_eq!(inner[0]["Salary"], 1500000);
+
+        let left = join(JoinKind::Left)?;
+        assert_eq!(left.len(), 27);
+        assert_eq!(left[0]["Salary"], Value::Null);
+
+        let full = join(JoinKind::Full)?;
+        assert_eq!(full.len(), 28);
+        let last = &full[27];
+        assert_eq!(last["Name"], "Dejan Kulusevski");
+        assert_eq!(last["Position"], Value::Null);
+        assert_eq!(last["Contract Until"], 2024);
+        Ok(())
+    }
+
+    #[test]
+    fn test_join_column_clash() -> Result<()> {
+        let rows = process_csv_join(
+            "assets/juventus.csv",
+            "fixtures/juventus-loans.csv",
+            &["Name".to_string()],
+            JoinKind::Full,
+            &CsvReadOpts::default(),
+        )?;
+        let kulusevski = &rows[27];
+        assert_eq!(kulusevski["Kit Number"], Value::Null);
+        assert_eq!(kulusevski["Kit Number_right"], "44");
+        assert_eq!(rows.len(), 29);
+        Ok(())
+    }
+
+    #[test]
+    fn test_join_key_cells() -> Result<()> {
+        let dir = tempfile::tempdir()?;
+        let left = dir.path().join("left.csv");
+        let right = dir.path().join("right.csv");
+        std::fs::write(&left, "id,a\n1,x\n,y\n")?;
+        std::fs::write(&right, "id,b\n1.0,p\n,q\n2.5,r\n")?;
+        let join = |kind| {
+            process_csv_join(
+                left.to_str().unwrap(),
+                right.to_str().unwrap(),
+                &["id".to_string()],
+                kind,
+                &CsvReadOpts {
+                    infer: true,
+                    ..Default::default()
+                },
+            )
+        };
+        let inner = join(JoinKind::Inner)?;
+        assert_eq!(inner, [serde_json::json!({"id": 1, "a": "x", "b": "p"})]);
+        // the empty keys match nothing, not each other
+        assert_eq!(join(JoinKind::Full)?.len(), 4);
+
+        // a file with no rows still has its columns
+        std::fs::write(&right, "id,b\n")?;
+        let left_rows = join(JoinKind::Left)?;
+        assert_eq!(left_rows.len(), 2);
+        assert_eq!(left_rows[0]["b"], Value::Null);
+        Ok(())
+    }
+}
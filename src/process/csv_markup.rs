@@ -0,0 +1,192 @@
+use std::fmt::Write;
+
+use serde_json::{Map, Value};
+
+use super::csv_convert::{cell_to_string, flatten_records, CsvWriteOpts};
+
+const STYLE: &str = "\
+table { border-collapse: collapse; font-family: sans-serif; font-size: 14px; }
+th, td { border: 1px solid #d0d7de; padding: 4px 10px; }
+th { background: #f6f8fa; text-align: left; }
+td.num { text-align: right; font-variant-numeric: tabular-nums; }
+tbody tr:nth-child(even) { background: #fafbfc; }
+th[aria-sort] { cursor: pointer; user-select: none; }
+th[aria-sort=ascending]::after { content: \" \\25B2\"; }
+th[aria-sort=descending]::after { content: \" \\25BC\"; }";
+
+/// Sorts the table by the clicked header, numbers numerically and text by locale, toggling
+/// the direction on each click.
+const SORT_SCRIPT: &str = "\
+document.querySelectorAll('table').forEach(function (table) {
+  var headers = table.querySelectorAll('th');
+  headers.forEach(function (th, col) {
+    th.setAttribute('aria-sort', 'none');
+    th.addEventListener('click', function () {
+      var asc = th.getAttribute('aria-sort') !== 'ascending';
+      headers.forEach(function (h) { h.setAttribute('aria-sort', 'none'); });
+      th.setAttribute('aria-sort', asc ? 'ascending' : 'descending');
+      var body = table.tBodies[0];
+      var rows = Array.prototype.slice.call(body.rows);
+      rows.sort(function (a, b) {
+        var x = a.cells[col].textContent, y = b.cells[col].textContent;
+        var nx = parseFloat(x), ny = parseFloat(y);
+        var cmp = !isNaN(nx) && !isNaN(ny) && isFinite(x) && isFinite(y)
+          ? nx - ny : x.localeCompare(y);
+        return asc ? cmp : -cmp;
+      });
+      rows.forEach(function (row) { body.appendChild(row); });
+    });
+  });
+});";
+
+/// Render records as a GitHub flavored markdown table, numeric columns aligned right.
+pub fn render_markdown(records: &[Value]) -> String {
+    let (headers, rows) = flatten_records(records);
+    if headers.is_empty() {
+        return String::new();
+    }
+    let numeric = numeric_columns(&headers, &rows);
+    let line = |cells: Vec<String>| format!("| {} |\n", cells.join(" | "));
+
+    let mut out = line(headers.iter().map(|h| escape_markdown(h)).collect());
+    out.push_str(&line(
+        numeric
+            .iter()
+            .map(|n| if *n { "---:" } else { "---" }.to_string())
+            .collect(),
+    ));
+    for row in &rows {
+        out.push_str(&line(
+            headers
+                .iter()
+                .map(|h| escape_markdown(&row.get(h).map(cell_to_string).unwrap_or_default()))
+                .collect(),
+        ));
+    }
+    out
+}
+
+/// Render records as a standalone html page holding a single table.
+pub fn render_html(records: &[Value], title: &str, opts: &CsvWriteOpts) -> String {
+    let (headers, rows) = flatten_records(records);
+    let numeric = numeric_columns(&headers, &rows);
+
+    let mut out = String::new();
+    // writing to a String can't fail
+    let _ = writeln!(
+        out,
+        "<!DOCTYPE html>\n<html>\n<head>\n<meta charset=\"{}\">\n<title>{}</title>\n<style>\n{}\n</style>\n</head>\n<body>\n<table>\n<thead>\n<tr>",
+        opts.encoding.name(),
+        escape_html(title),
+        STYLE
+    );
+    for header in &headers {
+        let _ = writeln!(out, "<th>{}</th>", escape_html(header));
+    }
+    out.push_str("</tr>\n</thead>\n<tbody>\n");
+    for row in &rows {
+        out.push_str("<tr>");
+        for (header, numeric) in headers.iter().zip(&numeric) {
+            let cell = row.get(header).map(cell_to_string).unwrap_or_default();
+            let class = if *numeric { " class=\"num\"" } else { "" };
+            let _ = write!(out, "<td{}>{}</td>", class, escape_html(&cell));
+        }
+        out.push_str("</tr>\n");
+    }
+    out.push_str("</tbody>\n</table>\n");
+    if opts.sortable {
+        let _ = writeln!(out, "<script>\n{}\n</script>", SORT_SCRIPT);
+    }
+    out.push_str("</body>\n</html>\n");
+    out
+}
+
+/// Columns holding nothing but numbers and empty cells.
+fn numeric_columns(headers: &[String], rows: &[Map<String, Value>]) -> Vec<bool> {
+    headers
+        .iter()
+        .map(|h| {
+            let values = rows
+                .iter()
+                .filter_map(|r| r.get(h))
+                .filter(|v| !v.is_null());
+            let mut values = values.peekable();
+            values.peek().is_some() && values.all(Value::is_number)
+        })
+        .collect()
+}
+
+/// Cells may hold anything, so markup in them is escaped like in html tables, and pipes are
+/// written as entities as not every renderer understands `\|`.
+fn escape_markdown(s: &str) -> String {
+    let mut out = String::with_capacity(s.len());
+    for c in s.replace("\r\n", "\n").chars() {
+        match c {
+            '\\' => out.push_str("\\\\"),
+            '|' => out.push_str("&#124;"),
+            '&' => out.push_str("&amp;"),
+            '<' => out.push_str("&lt;"),
+            '>' => out.push_str("&gt;"),
+            '\n' => out.push_str("<br>"),
+            c => out.push(c),
+        }
+    }
+    out
+}
+
+fn escape_html(s: &str) -> String {
+    let mut out = String::with_capacity(s.len());
+    for c in s.chars() {
+        match c {
+            '&' => out.push_str("&amp;"),
+            '<' => out.push_str("&lt;"),
+            '>' => out.push_str("&gt;"),
+            '"' => out.push_str("&quot;"),
+            '\'' => out.push_str("&#39;"),
+            c => out.push(c),
+        }
+    }
+    out
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use serde_json::json;
+
+    fn records() -> Vec<Value> {
+        vec![
+            json!({"name": "a|b", "kit": 1, "note": "<b>\"x\" & 'y'</b>"}),
+            json!({"name": "line\nbreak", "kit": null, "note": null}),
+        ]
+    }
+
+    #[test]
+    fn test_render_markdown() {
+        assert_eq!(
+            render_markdown(&records()),
+            "| name | kit | note |\n\
+             | --- | ---: | --- |\n\
+             | a&#124;b | 1 | &lt;b&gt;\"x\" &amp; 'y'&lt;/b&gt; |\n\
+             | line<br>break |  |  |\n"
+        );
+        assert_eq!(render_markdown(&[]), "");
+    }
+
+    #[test]
+    fn test_render_html() {
+        let html = render_html(&records(), "players & co", &CsvWriteOpts::default());
+        assert!(html.contains("<meta charset=\"UTF-8\">\n<title>players &amp; co</title>"));
+        assert!(html.contains("<th>name</th>\n<th>kit</th>"));
+        assert!(html.contains(
+            "<tr><td>a|b</td><td class=\"num\">1</td><td>&lt;b&gt;&quot;x&quot; &amp; &#39;y&#39;&lt;/b&gt;</td></tr>"
+        ));
+        assert!(!html.contains("<script>"));
+
+        let opts = CsvWriteOpts {
+            sortable: true,
+            ..Default::default()
+        };
+        assert!(render_html(&records(), "t", &opts).contains("<script>\ndocument.querySelectorAll"));
+    }
+}
@@ -0,0 +1,290 @@
+use std::{fs, io::Write, path::Path};
+
+use anyhow::{anyhow, bail, Result};
+use base64::{engine::general_purpose::STANDARD_NO_PAD, Engine};
+use chacha20poly1305::{
+    aead::{Aead, KeyInit, Payload},
+    ChaCha20Poly1305, Nonce,
+};
+use rand::{rngs::OsRng, RngCore};
+
+use crate::cli::ProtectMethod;
+
+use super::csv_convert::open_csv_dialect;
+use super::csv_dialect::CsvDialect;
+use super::text::KeyLoader;
+
+const MIN_KEY_LEN: usize = 32;
+const NONCE_LEN: usize = 12;
+/// Bytes of the keyed hash kept as a pseudonym, 128 bits make collisions negligible.
+const PSEUDONYM_LEN: usize = 16;
+
+/// Keys for protecting csv columns, both derived from the contents of one key file so the
+/// pseudonyms reveal nothing about the encryption key and vice versa.
+pub struct ColumnKey {
+    hash: [u8; 32],
+    cipher: ChaCha20Poly1305,
+}
+
+impl KeyLoader for ColumnKey {
+    fn load(path: impl AsRef<Path>) -> Result<Self> {
+        let key = fs::read_to_string(path)?;
+        Self::try_new(key.trim().as_bytes())
+    }
+}
+
+impl ColumnKey {
+    fn try_new(key: &[u8]) -> Result<Self> {
+        if key.len() < MIN_KEY_LEN {
+            bail!(
+                "the key must be at least {} bytes, generate one with `rcli text generate`",
+                MIN_KEY_LEN
+            );
+        }
+        let hash = blake3::derive_key("rcli csv protect 2024 pseudonym", key);
+        let cipher = blake3::derive_key("rcli csv protect 2024 encryption", key);
+        Ok(Self {
+            hash,
+            cipher: ChaCha20Poly1305::new(&cipher.into()),
+        })
+    }
+
+    /// The same value always gets the same pseudonym under the same key, whatever column
+    /// it's in, so protected files can still be joined.
+    fn pseudonym(&self, value: &str) -> String {
+        let hash = blake3::keyed_hash(&self.hash, value.as_bytes());
+        hash.as_bytes()[..PSEUDONYM_LEN]
+            .iter()
+            .map(|b| format!("{:02x}", b))
+            .collect()
+    }
+
+    /// Encrypt with a random nonce stored in front of the ciphertext. The column name is
+    /// authenticated too, so a value can't be moved to another column unnoticed.
+    fn encrypt(&self, column: &str, value: &str) -> Result<String> {
+        let mut nonce = [0u8; NONCE_LEN];
+        OsRng.fill_bytes(&mut nonce);
+        let payload = Payload {
+            msg: value.as_bytes(),
+            aad: column.as_bytes(),
+        };
+        let encrypted = self
+            .cipher
+            .encrypt(Nonce::from_slice(&nonce), payload)
+            .map_err(|e| anyhow!(e))?;
+        Ok(STANDARD_NO_PAD.encode([&nonce[..], &encrypted].concat()))
+    }
+
+    fn decrypt(&self, column: &str, value: &str) -> Result<String> {
+        let bytes = STANDARD_NO_PAD
+            .decode(value)
+            .map_err(|_| anyhow!("not an encrypted value"))?;
+        if bytes.len() < NONCE_LEN {
+            bail!("not an encrypted value");
+        }
+        let (nonce, encrypted) = bytes.split_at(NONCE_LEN);
+        let payload = Payload {
+            msg: encrypted,
+            aad: column.as_bytes(),
+        };
+        let plain = self
+            .cipher
+            .decrypt(Nonce::from_slice(nonce), payload)
+            .map_err(|_| anyhow!("wrong key, or the value was tampered with"))?;
+        Ok(String::from_utf8(plain)?)
+    }
+}
+
+/// Replace the cells of `columns` with pseudonyms or ciphertext. Empty cells stay empty.
+pub fn process_csv_protect(
+    input: &str,
+    dialect: &CsvDialect,
+    columns: &[String],
+    key: &str,
+    method: ProtectMethod,
+    output: impl Write,
+) -> Result<()> {
+    let key = ColumnKey::load(key)?;
+    rewrite_columns(
+        input,
+        dialect,
+        columns,
+        output,
+        |column, cell| match method {
+            ProtectMethod::Pseudonym => Ok(key.pseudonym(cell)),
+            ProtectMethod::Encrypt => key.encrypt(column, cell),
+        },
+    )
+}
+
+/// Decrypt the cells of `columns` encrypted by [`process_csv_protect`].
+pub fn process_csv_unprotect(
+    input: &str,
+    dialect: &CsvDialect,
+    columns: &[String],
+    key: &str,
+    output: impl Write,
+) -> Result<()> {
+    let key = ColumnKey::load(key)?;
+    rewrite_columns(input, dialect, columns, output, |column, cell| {
+        key.decrypt(column, cell)
+    })
+}
+
+/// Copy a csv file row by row, passing the non-empty cells of `columns` through `f`.
+fn rewrite_columns(
+    input: &str,
+    dialect: &CsvDialect,
+    columns: &[String],
+    output: impl Write,
+    mut f: impl FnMut(&str, &str) -> Result<String>,
+) -> Result<()> {
+    let (mut rdr, dialect) = open_csv_dialect(input, dialect)?;
+    let headers = rdr.headers()?.clone();
+    let selected = columns
+        .iter()
+        .map(|name| {
+            headers
+                .iter()
+                .position(|h| h == name)
+                .ok_or_else(|| anyhow!("column {:?} not found in {}", name, input))
+        })
+        .collect::<Result<Vec<_>>>()?;
+
+    let mut wtr = dialect.writer(output);
+    // the generated column names of headerless input aren't part of it
+    if dialect.has_headers {
+        wtr.write_record(&headers)?;
+    }
+    let mut record = csv::StringRecord::new();
+    while rdr.read_record(&mut record)? {
+        let mut row = record.iter().map(String::from).collect::<Vec<_>>();
+        for &i in &selected {
+            let Some(cell) = row.get_mut(i).filter(|c| !c.is_empty()) else {
+                continue;
+            };
+            *cell = f(&headers[i], cell).map_err(|e| {
+                let line = record.position().map(|p| p.line()).unwrap_or_default();
+                anyhow!("line {}, column {:?}: {}", line, &headers[i], e)
+            })?;
+        }
+        wtr.write_record(&row)?;
+    }
+    wtr.flush()?;
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const KEY: &str = "fixtures/csv-protect.key";
+
+    fn protect(method: ProtectMethod) -> Result<String> {
+        let columns = ["Name".to_string(), "DOB".to_string()];
+        let mut out = Vec::new();
+        process_csv_protect(
+            "assets/juventus.csv",
+            &CsvDialect::default(),
+            &columns,
+            KEY,
+            method,
+            &mut out,
+        )?;
+        Ok(String::from_utf8(out)?)
+    }
+
+    fn rows(csv: &str) -> Vec<csv::StringRecord> {
+        csv::Reader::from_reader(csv.as_bytes())
+            .records()
+            .collect::<Result<_, _>>()
+            .unwrap()
+    }
+
+    #[test]
+    fn test_pseudonyms_are_deterministic() -> Result<()> {
+        let first = protect(ProtectMethod::Pseudonym)?;
+        assert_eq!(first, protect(ProtectMethod::Pseudonym)?);
+        let rows = rows(&first);
+        assert_eq!(rows[0][0].len(), PSEUDONYM_LEN * 2);
+        assert_ne!(&rows[0][0], "Wojciech Szczesny");
+        assert_eq!(&rows[0][1], "Goalkeeper");
+        Ok(())
+    }
+
+    #[test]
+    fn test_encrypt_round_trip() -> Result<()> {
+        let protected = protect(ProtectMethod::Encrypt)?;
+        // a fresh nonce for every cell
+        assert_ne!(protected, protect(ProtectMethod::Encrypt)?);
+
+        let dir = tempfile::tempdir()?;
+        let path = dir.path().join("protected.csv");
+        fs::write(&path, &protected)?;
+        let mut out = Vec::new();
+        let columns = ["Name".to_string(), "DOB".to_string()];
+        process_csv_unprotect(
+            path.to_str().unwrap(),
+            &CsvDialect::default(),
+            &columns,
+            KEY,
+            &mut out,
+        )?;
+        assert_eq!(
+            String::from_utf8(out)?,
+            fs::read_to_string("assets/juventus.csv")?
+        );
+
+        // swapping the columns breaks the authentication
+        let mut out = Vec::new();
+        let swapped = ["DOB".to_string(), "Name".to_string()];
+        let header = protected.lines().next().unwrap();
+        let body = protected.replacen(header, "DOB,Position,Name,Nationality,Kit Number", 1);
+        fs::write(&path, body)?;
+        let err = process_csv_unprotect(
+            path.to_str().unwrap(),
+            &CsvDialect::default(),
+            &swapped,
+            KEY,
+            &mut out,
+        )
+        .unwrap_err();
+        assert_eq!(
+            err.to_string(),
+            "line 2, column \"DOB\": wrong key, or the value was tampered with"
+        );
+        Ok(())
+    }
+
+    #[test]
+    fn test_protect_keeps_dialect() -> Result<()> {
+        let dir = tempfile::tempdir()?;
+        let path = dir.path().join("players.csv");
+        fs::write(&path, "Dybala;10\nPogba;6\n")?;
+        let dialect = CsvDialect {
+            delimiter: b';',
+            has_headers: false,
+            ..Default::default()
+        };
+        let mut out = Vec::new();
+        process_csv_protect(
+            path.to_str().unwrap(),
+            &dialect,
+            &["column1".to_string()],
+            KEY,
+            ProtectMethod::Pseudonym,
+            &mut out,
+        )?;
+        let out = String::from_utf8(out)?;
+        let lines = out.lines().collect::<Vec<_>>();
+        assert_eq!(lines.len(), 2);
+        assert!(lines[0].ends_with(";10"));
+        assert_eq!(lines[0].len(), PSEUDONYM_LEN * 2 + 3);
+        Ok(())
+    }
+
+    #[test]
+    fn test_short_key() {
+        assert!(ColumnKey::try_new(b"too short").is_err());
+    }
+}
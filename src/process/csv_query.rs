@@ -0,0 +1,460 @@
+use std::{cmp::Ordering, collections::HashMap, path::Path};
+
+use anyhow::{anyhow, bail, Result};
+use serde_json::{Map, Value};
+
+use crate::cli::InputFormat;
+
+use super::csv_convert::{read_records, CsvReadOpts};
+use super::csv_dialect::CsvDialect;
+use super::csv_expr::{is_aggregate, number, sort_order, to_number, truthy, Expr, Parser, Token};
+
+#[derive(Debug, PartialEq)]
+pub struct Query {
+    pub projection: Vec<SelectItem>,
+    pub table: String,
+    pub filter: Option<Expr>,
+    pub group_by: Vec<Expr>,
+    pub having: Option<Expr>,
+    pub order_by: Vec<(OrderKey, bool)>,
+    pub limit: Option<usize>,
+    pub offset: usize,
+}
+
+#[derive(Debug, PartialEq)]
+pub enum SelectItem {
+    Wildcard,
+    Expr(Expr, Option<String>),
+}
+
+#[derive(Debug, PartialEq)]
+pub enum OrderKey {
+    /// 1-based position in the select list
+    Position(usize),
+    Expr(Expr),
+}
+
+type Row = Map<String, Value>;
+
+/// Run a `SELECT ... FROM ...` query over a csv file. Without `input`, the table name in the
+/// FROM clause is taken as the path of the csv file, with or without the `.csv` extension.
+pub fn process_csv_query(
+    sql: &str,
+    input: Option<&str>,
+    dialect: &CsvDialect,
+) -> Result<Vec<Value>> {
+    let query = Query::parse(sql)?;
+    let path = match input {
+        Some(input) => {
+            let stem = Path::new(input).file_stem().and_then(|s| s.to_str());
+            if input != "-" && !stem.is_some_and(|s| s.eq_ignore_ascii_case(&query.table)) {
+                bail!(
+                    "unknown table {:?}, expected {:?}",
+                    query.table,
+                    stem.unwrap_or(input)
+                );
+            }
+            input.to_string()
+        }
+        None if Path::new(&query.table).is_file() => query.table.clone(),
+        None => format!("{}.csv", query.table),
+    };
+
+    let opts = CsvReadOpts {
+        infer: true,
+        dialect: dialect.clone(),
+        ..Default::default()
+    };
+    let records = read_records(&path, InputFormat::Csv, &opts)?;
+    let rows = records
+        .into_iter()
+        .filter_map(|v| match v {
+            Value::Object(row) => Some(row),
+            _ => None,
+        })
+        .collect::<Vec<_>>();
+    query.execute(&rows)
+}
+
+impl Query {
+    pub fn parse(sql: &str) -> Result<Self> {
+        let mut p = Parser::new(sql)?;
+        p.expect_keyword("select")?;
+        let mut projection = Vec::new();
+        loop {
+            if p.eat_symbol("*") {
+                projection.push(SelectItem::Wildcard);
+            } else {
+                let expr = p.parse_expr()?;
+                let alias = if p.eat_keyword("as") {
+                    Some(p.parse_ident()?)
+                } else {
+                    None
+                };
+                projection.push(SelectItem::Expr(expr, alias));
+            }
+            if !p.eat_symbol(",") {
+                break;
+            }
+        }
+
+        p.expect_keyword("from")?;
+        let table = match p.next() {
+            Some(Token::Ident(s)) | Some(Token::QuotedIdent(s)) | Some(Token::Str(s)) => s,
+            _ => bail!("expected a table name after FROM"),
+        };
+
+        let filter = if p.eat_keyword("where") {
+            Some(p.parse_expr()?)
+        } else {
+            None
+        };
+
+        let mut group_by = Vec::new();
+        if p.eat_keyword("group") {
+            p.expect_keyword("by")?;
+            loop {
+                group_by.push(p.parse_expr()?);
+                if !p.eat_symbol(",") {
+                    break;
+                }
+            }
+        }
+
+        let having = if p.eat_keyword("having") {
+            Some(p.parse_expr()?)
+        } else {
+            None
+        };
+
+        let mut order_by = Vec::new();
+        if p.eat_keyword("order") {
+            p.expect_keyword("by")?;
+            loop {
+                let key = match p.parse_expr()? {
+                    Expr::Literal(Value::Number(n)) => {
+                        let n = n
+                            .as_u64()
+                            .filter(|n| *n > 0)
+                            .ok_or_else(|| anyhow!("invalid ORDER BY position {}", n))?;
+                        OrderKey::Position(n as usize)
+                    }
+                    expr => OrderKey::Expr(expr),
+                };
+                let desc = if p.eat_keyword("desc") {
+                    true
+                } else {
+                    p.eat_keyword("asc");
+                    false
+                };
+                order_by.push((key, desc));
+                if !p.eat_symbol(",") {
+                    break;
+                }
+            }
+        }
+
+        let limit = if p.eat_keyword("limit") {
+            Some(p.parse_number()?)
+        } else {
+            None
+        };
+        let offset = if p.eat_keyword("offset") {
+            p.parse_number()?
+        } else {
+            0
+        };
+        if !p.is_done() {
+            bail!("unexpected {} in query", p.describe_next());
+        }
+
+        Ok(Self {
+            projection,
+            table,
+            filter,
+            group_by,
+            having,
+            order_by,
+            limit,
+            offset,
+        })
+    }
+
+    pub fn execute(&self, rows: &[Row]) -> Result<Vec<Value>> {
+        let mut matched = Vec::new();
+        for row in rows {
+            let keep = match &self.filter {
+                Some(filter) => truthy(&filter.eval(row)?),
+                None => true,
+            };
+            if keep {
+                matched.push(row);
+            }
+        }
+
+        let aggregated = !self.group_by.is_empty()
+            || self.having.is_some()
+            || self.projection.iter().any(|item| match item {
+                SelectItem::Expr(expr, _) => expr.has_aggregate(),
+                SelectItem::Wildcard => false,
+            });
+        let mut groups = if aggregated {
+            self.group(&matched)?
+        } else {
+            matched.into_iter().map(|row| vec![row]).collect()
+        };
+        if let Some(having) = &self.having {
+            let mut kept = Vec::new();
+            for group in groups {
+                if truthy(&eval_group(having, &group)?) {
+                    kept.push(group);
+                }
+            }
+            groups = kept;
+        }
+
+        let mut output = Vec::with_capacity(groups.len());
+        for group in &groups {
+            output.push(self.project(group, aggregated)?);
+        }
+
+        if !self.order_by.is_empty() {
+            let mut keyed = Vec::with_capacity(output.len());
+            for (row, group) in output.into_iter().zip(&groups) {
+                keyed.push((self.sort_keys(&row, group)?, row));
+            }
+            keyed.sort_by(|(a, _), (b, _)| {
+                a.iter()
+                    .zip(b)
+                    .zip(&self.order_by)
+                    .map(|((a, b), (_, desc))| {
+                        let ord = sort_order(a, b);
+                        if *desc {
+                            ord.reverse()
+                        } else {
+                            ord
+                        }
+                    })
+                    .find(|ord| *ord != Ordering::Equal)
+                    .unwrap_or(Ordering::Equal)
+            });
+            output = keyed.into_iter().map(|(_, row)| row).collect();
+        }
+
+        Ok(output
+            .into_iter()
+            .skip(self.offset)
+            .take(self.limit.unwrap_or(usize::MAX))
+            .map(Value::Object)
+            .collect())
+    }
+
+    /// Split rows into groups by the GROUP BY keys, keeping first seen order.
+    fn group<'a>(&self, rows: &[&'a Row]) -> Result<Vec<Vec<&'a Row>>> {
+        if self.group_by.is_empty() {
+            return Ok(vec![rows.to_vec()]);
+        }
+        let mut index = HashMap::new();
+        let mut groups: Vec<Vec<&Row>> = Vec::new();
+        for row in rows {
+            let key = self
+                .group_by
+                .iter()
+                .map(|e| e.eval(row))
+                .collect::<Result<Vec<_>>>()?;
+            let key = serde_json::to_string(&key)?;
+            let i = *index.entry(key).or_insert_with(|| {
+                groups.push(Vec::new());
+                groups.len() - 1
+            });
+            groups[i].push(row);
+        }
+        Ok(groups)
+    }
+
+    fn project(&self, group: &[&Row], aggregated: bool) -> Result<Row> {
+        let mut out = Map::new();
+        for item in &self.projection {
+            match item {
+                SelectItem::Wildcard if aggregated => {
+                    bail!("SELECT * can't be used with GROUP BY or aggregates")
+                }
+                SelectItem::Wildcard => {
+                    if let Some(row) = group.first() {
+                        out.extend(row.iter().map(|(k, v)| (k.clone(), v.clone())));
+                    }
+                }
+                SelectItem::Expr(expr, alias) => {
+                    let name = match (alias, expr) {
+                        (Some(alias), _) => alias.clone(),
+                        (None, expr) => expr.to_string(),
+                    };
+                    out.insert(name, eval_group(expr, group)?);
+                }
+            }
+        }
+        Ok(out)
+    }
+
+    fn sort_keys(&self, row: &Row, group: &[&Row]) -> Result<Vec<Value>> {
+        self.order_by
+            .iter()
+            .map(|(key, _)| match key {
+                OrderKey::Position(i) => row
+                    .values()
+                    .nth(i - 1)
+                    .cloned()
+                    .ok_or_else(|| anyhow!("ORDER BY position {} is out of range", i)),
+                // output columns and aliases take precedence over source columns
+                OrderKey::Expr(Expr::Column(name)) if row.contains_key(name) => {
+                    Ok(row[name].clone())
+                }
+                OrderKey::Expr(expr) => eval_group(expr, group),
+            })
+            .collect()
+    }
+}
+
+/// Evaluate an expression over a group of rows: aggregates see every row, plain column
+/// references see the first one.
+fn eval_group(expr: &Expr, rows: &[&Row]) -> Result<Value> {
+    let expr = resolve_aggregates(expr, rows)?;
+    let empty = Map::new();
+    expr.eval(rows.first().copied().unwrap_or(&empty))
+}
+
+fn resolve_aggregates(expr: &Expr, rows: &[&Row]) -> Result<Expr> {
+    let resolve = |e: &Expr| resolve_aggregates(e, rows).map(Box::new);
+    let expr = match expr {
+        Expr::Call(name, args) if is_aggregate(name) => Expr::Literal(aggregate(name, args, rows)?),
+        Expr::Call(name, args) => Expr::Call(
+            name.clone(),
+            args.iter()
+                .map(|a| resolve_aggregates(a, rows))
+                .collect::<Result<_>>()?,
+        ),
+        Expr::Neg(e) => Expr::Neg(resolve(e)?),
+        Expr::Not(e) => Expr::Not(resolve(e)?),
+        Expr::Binary(l, op, r) => Expr::Binary(resolve(l)?, *op, resolve(r)?),
+        Expr::IsNull(e, negated) => Expr::IsNull(resolve(e)?, *negated),
+        Expr::Like(e, p, negated) => Expr::Like(resolve(e)?, resolve(p)?, *negated),
+        Expr::In(e, items, negated) => Expr::In(
+            resolve(e)?,
+            items
+                .iter()
+                .map(|i| resolve_aggregates(i, rows))
+                .collect::<Result<_>>()?,
+            *negated,
+        ),
+        e => e.clone(),
+    };
+    Ok(expr)
+}
+
+fn aggregate(name: &str, args: &[Expr], rows: &[&Row]) -> Result<Value> {
+    let [arg] = args else {
+        bail!("{}() expects exactly one argument", name);
+    };
+    if name == "count" && *arg == Expr::Wildcard {
+        return Ok(Value::from(rows.len()));
+    }
+    if arg.has_aggregate() {
+        bail!("aggregates can't be nested in {}()", name);
+    }
+
+    let mut values = Vec::with_capacity(rows.len());
+    for row in rows {
+        let v = arg.eval(row)?;
+        if !v.is_null() {
+            values.push(v);
+        }
+    }
+    let numbers = values.iter().filter_map(to_number).collect::<Vec<_>>();
+    let value = match name {
+        "count" => Value::from(values.len()),
+        _ if values.is_empty() => Value::Null,
+        "sum" => number(numbers.iter().sum()),
+        // values that aren't numbers count towards neither the sum nor the divisor
+        "avg" if numbers.is_empty() => Value::Null,
+        "avg" => number(numbers.iter().sum::<f64>() / numbers.len() as f64),
+        "min" => values.into_iter().min_by(sort_order).unwrap_or_default(),
+        "max" => values.into_iter().max_by(sort_order).unwrap_or_default(),
+        _ => unreachable!(),
+    };
+    Ok(value)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use serde_json::json;
+
+    fn query(sql: &str) -> Result<Vec<Value>> {
+        process_csv_query(sql, Some("assets/juventus.csv"), &CsvDialect::default())
+    }
+
+    #[test]
+    fn test_parse_query() -> Result<()> {
+        let q = Query::parse(
+            r#"SELECT Name, "Kit Number" AS kit FROM juventus WHERE Nationality = 'Italy' ORDER BY 2 DESC LIMIT 3"#,
+        )?;
+        assert_eq!(q.table, "juventus");
+        assert_eq!(q.projection.len(), 2);
+        assert_eq!(q.order_by, [(OrderKey::Position(2), true)]);
+        assert_eq!(q.limit, Some(3));
+        assert!(Query::parse("SELECT FROM t").is_err());
+        assert!(Query::parse("SELECT a FROM t LIMIT x").is_err());
+        Ok(())
+    }
+
+    #[test]
+    fn test_query_filter_order_limit() -> Result<()> {
+        let rows = query(
+            r#"SELECT Name, "Kit Number" FROM juventus WHERE Nationality = 'Italy' ORDER BY 2 LIMIT 2"#,
+        )?;
+        assert_eq!(
+            rows,
+            [
+                json!({"Name": "Mattia De Sciglio", "Kit Number": 2}),
+                json!({"Name": "Giorgio Chiellini", "Kit Number": 3}),
+            ]
+        );
+        Ok(())
+    }
+
+    #[test]
+    fn test_query_group_by() -> Result<()> {
+        let rows = query(
+            r#"SELECT Position, count(*) AS n, max("Kit Number") AS top FROM juventus GROUP BY Position HAVING count(*) > 4 ORDER BY n DESC"#,
+        )?;
+        assert_eq!(
+            rows[0],
+            json!({"Position": "Central Midfield", "n": 6, "top": 30})
+        );
+        assert_eq!(rows.len(), 2);
+
+        let rows = query(r#"SELECT count(*), avg("Kit Number") FROM juventus WHERE 1 = 0"#)?;
+        assert_eq!(rows, [json!({"count(*)": 0, "avg(Kit Number)": null})]);
+
+        let dir = tempfile::tempdir()?;
+        let path = dir.path().join("goals.csv");
+        std::fs::write(&path, "player,goals\na,2\nb,n/a\nc,4\n")?;
+        let rows = process_csv_query(
+            "SELECT avg(goals), count(goals), avg(player) FROM goals",
+            path.to_str(),
+            &CsvDialect::default(),
+        )?;
+        assert_eq!(
+            rows,
+            [json!({"avg(goals)": 3, "count(goals)": 3, "avg(player)": null})]
+        );
+        Ok(())
+    }
+
+    #[test]
+    fn test_query_errors() {
+        assert!(query("SELECT * FROM players").is_err());
+        assert!(query("SELECT Nope FROM juventus").is_err());
+        assert!(query("SELECT * FROM juventus GROUP BY Position").is_err());
+    }
+}
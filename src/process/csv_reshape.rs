@@ -0,0 +1,353 @@
+use std::{cmp::Ordering, collections::HashMap};
+
+use anyhow::{anyhow, bail, Result};
+use serde_json::{Map, Value};
+
+use crate::cli::Aggregate;
+
+use super::csv_convert::open_csv;
+use super::csv_dialect::CsvDialect;
+use super::csv_expr::{number, sort_order, to_number};
+use super::csv_schema::ColumnType;
+
+/// What to pivot: one output row per distinct `rows` key, one output column per distinct
+/// value of `column`, cells aggregating `values` (just counting rows if omitted).
+#[derive(Debug)]
+pub struct PivotSpec<'a> {
+    pub rows: &'a [String],
+    pub column: &'a str,
+    pub values: Option<&'a str>,
+    pub aggregate: Aggregate,
+    /// parse cells into numbers, booleans... instead of keeping strings
+    pub infer: bool,
+}
+
+/// Running state of one pivot cell.
+#[derive(Debug, Default)]
+struct Accumulator {
+    count: usize,
+    sum: f64,
+    min: Option<Value>,
+    max: Option<Value>,
+}
+
+impl Accumulator {
+    fn add(&mut self, value: Value, aggregate: Aggregate) -> Result<(), Value> {
+        self.count += 1;
+        match aggregate {
+            Aggregate::Count => {}
+            Aggregate::Sum | Aggregate::Avg => self.sum += to_number(&value).ok_or(value)?,
+            Aggregate::Min | Aggregate::Max => {
+                if self
+                    .min
+                    .as_ref()
+                    .is_none_or(|m| sort_order(&value, m) == Ordering::Less)
+                {
+                    self.min = Some(value.clone());
+                }
+                if self
+                    .max
+                    .as_ref()
+                    .is_none_or(|m| sort_order(&value, m) == Ordering::Greater)
+                {
+                    self.max = Some(value);
+                }
+            }
+        }
+        Ok(())
+    }
+
+    fn finish(&self, aggregate: Aggregate) -> Value {
+        match aggregate {
+            Aggregate::Count => Value::from(self.count),
+            _ if self.count == 0 => Value::Null,
+            Aggregate::Sum => number(self.sum),
+            Aggregate::Avg => number(self.sum / self.count as f64),
+            Aggregate::Min => self.min.clone().unwrap_or_default(),
+            Aggregate::Max => self.max.clone().unwrap_or_default(),
+        }
+    }
+}
+
+/// Reshape a long csv file into a wide table. Row keys and pivot columns keep the order they
+/// are first seen in; empty value cells are skipped. Pivot values become column names, so
+/// they can't be empty or named like a row column.
+pub fn process_csv_pivot(
+    input: &str,
+    dialect: &CsvDialect,
+    spec: &PivotSpec,
+) -> Result<Vec<Value>> {
+    if spec.values.is_none() && spec.aggregate != Aggregate::Count {
+        bail!("{:?} needs a values column to aggregate", spec.aggregate);
+    }
+    let mut rdr = open_csv(input, dialect)?;
+    let headers = rdr.headers()?.clone();
+    let position = |name: &str| {
+        headers
+            .iter()
+            .position(|h| h == name)
+            .ok_or_else(|| anyhow!("column {:?} not found in {}", name, input))
+    };
+    let row_columns = spec
+        .rows
+        .iter()
+        .map(|r| position(r))
+        .collect::<Result<Vec<_>>>()?;
+    let pivot_column = position(spec.column)?;
+    let value_column = spec.values.map(position).transpose()?;
+
+    let mut keys: Vec<Vec<String>> = Vec::new();
+    let mut key_index = HashMap::new();
+    let mut columns: Vec<String> = Vec::new();
+    let mut cells: HashMap<(usize, String), Accumulator> = HashMap::new();
+
+    let mut record = csv::StringRecord::new();
+    while rdr.read_record(&mut record)? {
+        let key = row_columns
+            .iter()
+            .map(|i| record.get(*i).unwrap_or_default().to_string())
+            .collect::<Vec<_>>();
+        let row = *key_index.entry(key.clone()).or_insert_with(|| {
+            keys.push(key);
+            keys.len() - 1
+        });
+        let line = record.position().map(|p| p.line()).unwrap_or_default();
+        let column = record.get(pivot_column).unwrap_or_default().to_string();
+        if !columns.contains(&column) {
+            if column.is_empty() {
+                bail!(
+                    "row {}: empty {:?} cell, it can't name a column",
+                    line,
+                    spec.column
+                );
+            }
+            if spec.rows.contains(&column) {
+                bail!(
+                    "row {}: {:?} cell {:?} clashes with the row column of that name",
+                    line,
+                    spec.column,
+                    column
+                );
+            }
+            columns.push(column.clone());
+        }
+
+        let acc = cells.entry((row, column)).or_default();
+        let value = match value_column {
+            Some(i) => match record.get(i).unwrap_or_default() {
+                "" => continue,
+                cell => parse_cell(cell, spec.infer),
+            },
+            None => Value::Bool(true),
+        };
+        if value.is_null() {
+            continue;
+        }
+        acc.add(value, spec.aggregate).map_err(|v| {
+            anyhow!(
+                "row {}, column {:?}: {} is not a number",
+                line,
+                spec.values.unwrap_or_default(),
+                v
+            )
+        })?;
+    }
+
+    Ok(keys
+        .into_iter()
+        .enumerate()
+        .map(|(row, key)| {
+            let mut out = spec
+                .rows
+                .iter()
+                .zip(key)
+                .map(|(name, cell)| (name.clone(), parse_cell(&cell, spec.infer)))
+                .collect::<Map<_, _>>();
+            for column in &columns {
+                let value = match cells.get(&(row, column.clone())) {
+                    Some(acc) => acc.finish(spec.aggregate),
+                    None => Accumulator::default().finish(spec.aggregate),
+                };
+                out.insert(column.clone(), value);
+            }
+            Value::Object(out)
+        })
+        .collect())
+}
+
+/// Reshape a wide csv file into a long one: every `values` column of a row (all but the `ids`
+/// if none are given) becomes its own row of ids, column name and value.
+pub fn process_csv_melt(
+    input: &str,
+    dialect: &CsvDialect,
+    ids: &[String],
+    values: &[String],
+    names: (&str, &str),
+    infer: bool,
+) -> Result<Vec<Value>> {
+    let (var_name, value_name) = names;
+    let mut rdr = open_csv(input, dialect)?;
+    let headers = rdr.headers()?.clone();
+    for column in ids.iter().chain(values) {
+        if !headers.iter().any(|h| h == column) {
+            bail!("column {:?} not found in {}", column, input);
+        }
+    }
+    if let Some(clash) = ids.iter().find(|id| *id == var_name || *id == value_name) {
+        bail!("id column {:?} clashes with an output column name", clash);
+    }
+    let is_value = |h: &str| match values.is_empty() {
+        true => !ids.iter().any(|id| id == h),
+        false => values.iter().any(|v| v == h),
+    };
+
+    let mut rows = Vec::new();
+    let mut record = csv::StringRecord::new();
+    while rdr.read_record(&mut record)? {
+        let id_cells = ids
+            .iter()
+            .map(|id| {
+                let i = headers.iter().position(|h| h == id).unwrap_or_default();
+                (
+                    id.clone(),
+                    parse_cell(record.get(i).unwrap_or_default(), infer),
+                )
+            })
+            .collect::<Map<_, _>>();
+        for (header, cell) in headers.iter().zip(record.iter()) {
+            if !is_value(header) {
+                continue;
+            }
+            let mut row = id_cells.clone();
+            row.insert(var_name.to_string(), Value::from(header));
+            row.insert(value_name.to_string(), parse_cell(cell, infer));
+            rows.push(Value::Object(row));
+        }
+    }
+    Ok(rows)
+}
+
+fn parse_cell(cell: &str, infer: bool) -> Value {
+    let kind = if infer {
+        ColumnType::infer(cell)
+    } else {
+        ColumnType::String
+    };
+    kind.parse(cell)
+        .unwrap_or_else(|_| Value::String(cell.to_string()))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use serde_json::json;
+
+    fn pivot(values: Option<&str>, aggregate: Aggregate) -> Result<Vec<Value>> {
+        let rows = ["Nationality".to_string()];
+        let spec = PivotSpec {
+            rows: &rows,
+            column: "Position",
+            values,
+            aggregate,
+            infer: true,
+        };
+        process_csv_pivot("assets/juventus.csv", &CsvDialect::default(), &spec)
+    }
+
+    #[test]
+    fn test_process_csv_pivot() -> Result<()> {
+        let counts = pivot(None, Aggregate::Count)?;
+        assert_eq!(counts.len(), 14);
+        assert_eq!(counts[1]["Nationality"], "Italy");
+        assert_eq!(counts[1]["Goalkeeper"], 3);
+        assert_eq!(counts[1]["Centre-Back"], 3);
+        assert_eq!(counts[1]["Left Winger"], 0);
+
+        let max = pivot(Some("Kit Number"), Aggregate::Max)?;
+        assert_eq!(max[1]["Goalkeeper"], 77);
+        assert_eq!(max[1]["Left Winger"], Value::Null);
+        let avg = pivot(Some("Kit Number"), Aggregate::Avg)?;
+        assert_eq!(avg[1]["Centre-Back"], json!(15.333333333333334));
+
+        let err = pivot(Some("DOB"), Aggregate::Sum).unwrap_err();
+        assert_eq!(
+            err.to_string(),
+            "row 2, column \"DOB\": \"Apr 18, 1990 (29)\" is not a number"
+        );
+        assert!(pivot(None, Aggregate::Sum).is_err());
+        Ok(())
+    }
+
+    #[test]
+    fn test_pivot_values() -> Result<()> {
+        let rows = ["Nationality".to_string()];
+        let spec = PivotSpec {
+            rows: &rows,
+            column: "Position",
+            values: Some("Kit Number"),
+            aggregate: Aggregate::Max,
+            infer: false,
+        };
+        let max = process_csv_pivot("assets/juventus.csv", &CsvDialect::default(), &spec)?;
+        assert_eq!(max[1]["Goalkeeper"], "77");
+
+        let dir = tempfile::tempdir()?;
+        let input = dir.path().join("long.csv");
+        let spec = PivotSpec {
+            rows: &rows,
+            column: "Position",
+            values: None,
+            aggregate: Aggregate::Count,
+            infer: true,
+        };
+        for (data, err) in [
+            (
+                "Nationality,Position\nItaly,Nationality\n",
+                "row 2: \"Position\" cell \"Nationality\" clashes with the row column of that name",
+            ),
+            (
+                "Nationality,Position\nItaly,Goalkeeper\nPoland,\n",
+                "row 3: empty \"Position\" cell, it can't name a column",
+            ),
+        ] {
+            std::fs::write(&input, data)?;
+            let res = process_csv_pivot(input.to_str().unwrap(), &CsvDialect::default(), &spec);
+            assert_eq!(res.unwrap_err().to_string(), err);
+        }
+        Ok(())
+    }
+
+    #[test]
+    fn test_process_csv_melt() -> Result<()> {
+        let ids = ["Name".to_string()];
+        let values = ["Nationality".to_string(), "Kit Number".to_string()];
+        let rows = process_csv_melt(
+            "assets/juventus.csv",
+            &CsvDialect::default(),
+            &ids,
+            &values,
+            ("field", "value"),
+            true,
+        )?;
+        assert_eq!(rows.len(), 54);
+        assert_eq!(
+            rows[..2],
+            [
+                json!({"Name": "Wojciech Szczesny", "field": "Nationality", "value": "Poland"}),
+                json!({"Name": "Wojciech Szczesny", "field": "Kit Number", "value": 1}),
+            ]
+        );
+
+        let rows = process_csv_melt(
+            "assets/juventus.csv",
+            &CsvDialect::default(),
+            &ids,
+            &[],
+            ("variable", "value"),
+            false,
+        )?;
+        assert_eq!(rows.len(), 27 * 4);
+        assert_eq!(rows[3]["value"], "1");
+        Ok(())
+    }
+}
@@ -0,0 +1,259 @@
+use std::{fmt, fs, path::Path};
+
+use anyhow::{anyhow, bail, Result};
+use chrono::{DateTime, NaiveDate, NaiveDateTime};
+use serde::{Deserialize, Serialize};
+use serde_json::Value;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum ColumnType {
+    Null,
+    Boolean,
+    Integer,
+    Float,
+    Date,
+    Datetime,
+    String,
+}
+
+#[derive(Debug, Default, Clone, Deserialize)]
+pub struct CsvSchema {
+    #[serde(default)]
+    pub columns: Vec<ColumnSchema>,
+    /// reject columns the schema doesn't declare
+    #[serde(default)]
+    pub strict: bool,
+}
+
+#[derive(Debug, Clone, Deserialize)]
+pub struct ColumnSchema {
+    pub name: String,
+    #[serde(rename = "type")]
+    pub kind: Option<ColumnType>,
+    pub rename: Option<String>,
+    /// the column must be present in the header
+    #[serde(default)]
+    pub required: bool,
+    /// cells may be empty
+    #[serde(default = "default_true")]
+    pub nullable: bool,
+    #[serde(default)]
+    pub unique: bool,
+    pub pattern: Option<String>,
+    #[serde(rename = "enum")]
+    pub allowed: Option<Vec<String>>,
+    pub min: Option<f64>,
+    pub max: Option<f64>,
+    pub min_length: Option<usize>,
+    pub max_length: Option<usize>,
+}
+
+fn default_true() -> bool {
+    true
+}
+
+impl CsvSchema {
+    /// Load a schema from a yaml (or json) file.
+    pub fn load(path: impl AsRef<Path>) -> Result<Self> {
+        let content = fs::read_to_string(path)?;
+        Ok(serde_yaml::from_str(&content)?)
+    }
+
+    pub fn column(&self, name: &str) -> Option<&ColumnSchema> {
+        self.columns.iter().find(|c| c.name == name)
+    }
+
+    /// Check every column the schema declares is in `headers`, so a typo doesn't silently
+    /// leave a column untyped.
+    pub fn check_columns<'a>(&self, headers: impl IntoIterator<Item = &'a str>) -> Result<()> {
+        let headers = headers.into_iter().collect::<Vec<_>>();
+        let missing = self
+            .columns
+            .iter()
+            .filter(|c| !headers.contains(&c.name.as_str()))
+            .map(|c| format!("{:?}", c.name))
+            .collect::<Vec<_>>();
+        if !missing.is_empty() {
+            bail!(
+                "schema columns not found in the header: {}",
+                missing.join(", ")
+            );
+        }
+        Ok(())
+    }
+
+    /// The output name of a column after applying renames.
+    pub fn output_name<'a>(&'a self, name: &'a str) -> &'a str {
+        self.column(name)
+            .and_then(|c| c.rename.as_deref())
+            .unwrap_or(name)
+    }
+}
+
+impl ColumnType {
+    /// Infer the narrowest type of a single cell.
+    pub fn infer(s: &str) -> Self {
+        let s = s.trim();
+        if s.is_empty() {
+            ColumnType::Null
+        } else if s.eq_ignore_ascii_case("true") || s.eq_ignore_ascii_case("false") {
+            ColumnType::Boolean
+        } else if has_leading_zero(s) {
+            // keep identifiers like zip codes or "007" as they are
+            ColumnType::String
+        } else if s.parse::<i64>().is_ok() {
+            ColumnType::Integer
+        } else if s.parse::<f64>().is_ok_and(f64::is_finite) {
+            ColumnType::Float
+        } else if parse_date(s).is_some() {
+            ColumnType::Date
+        } else if parse_datetime(s).is_some() {
+            ColumnType::Datetime
+        } else {
+            ColumnType::String
+        }
+    }
+
+    /// Widen two types into one that fits both.
+    pub fn merge(self, other: Self) -> Self {
+        use ColumnType::*;
+        match (self, other) {
+            (a, b) if a == b => a,
+            (Null, t) | (t, Null) => t,
+            (Integer, Float) | (Float, Integer) => Float,
+            // neither type parses the other's cells, so a column mixing them is text
+            _ => String,
+        }
+    }
+
+    /// Convert a cell into a typed json value. Empty cells become null, except in string
+    /// columns, where they're empty strings.
+    pub fn parse(self, s: &str) -> Result<Value> {
+        let v = s.trim();
+        if self == ColumnType::String {
+            return Ok(Value::String(s.to_string()));
+        }
+        if v.is_empty() {
+            return Ok(Value::Null);
+        }
+        let value = match self {
+            ColumnType::Null | ColumnType::String => Value::Null,
+            ColumnType::Boolean => Value::Bool(v.to_ascii_lowercase().parse()?),
+            ColumnType::Integer => Value::from(v.parse::<i64>()?),
+            ColumnType::Float => {
+                let f = v.parse::<f64>()?;
+                serde_json::Number::from_f64(f)
+                    .map(Value::Number)
+                    .ok_or_else(|| anyhow!("{} is not a finite number", v))?
+            }
+            ColumnType::Date => {
+                parse_date(v).ok_or_else(|| anyhow!("{} is not an ISO date", v))?;
+                Value::String(v.to_string())
+            }
+            ColumnType::Datetime => {
+                parse_datetime(v).ok_or_else(|| anyhow!("{} is not an ISO datetime", v))?;
+                Value::String(v.to_string())
+            }
+        };
+        Ok(value)
+    }
+}
+
+impl fmt::Display for ColumnType {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let t = match self {
+            ColumnType::Null => "null",
+            ColumnType::Boolean => "boolean",
+            ColumnType::Integer => "integer",
+            ColumnType::Float => "float",
+            ColumnType::Date => "date",
+            ColumnType::Datetime => "datetime",
+            ColumnType::String => "string",
+        };
+        write!(f, "{}", t)
+    }
+}
+
+/// Infer a type for every column from all of its cells.
+pub fn infer_column_types<'a, I, R>(width: usize, rows: I) -> Vec<ColumnType>
+where
+    I: IntoIterator<Item = R>,
+    R: IntoIterator<Item = &'a str>,
+{
+    let mut types = vec![ColumnType::Null; width];
+    for row in rows {
+        for (t, cell) in types.iter_mut().zip(row) {
+            *t = t.merge(ColumnType::infer(cell));
+        }
+    }
+    types
+}
+
+pub fn parse_date(s: &str) -> Option<NaiveDate> {
+    NaiveDate::parse_from_str(s, "%Y-%m-%d").ok()
+}
+
+pub fn parse_datetime(s: &str) -> Option<NaiveDateTime> {
+    DateTime::parse_from_rfc3339(s)
+        .map(|dt| dt.naive_utc())
+        .or_else(|_| NaiveDateTime::parse_from_str(s, "%Y-%m-%dT%H:%M:%S"))
+        .or_else(|_| NaiveDateTime::parse_from_str(s, "%Y-%m-%d %H:%M:%S"))
+        .ok()
+}
+
+fn has_leading_zero(s: &str) -> bool {
+    let digits = s.strip_prefix('-').unwrap_or(s);
+    digits.len() > 1 && digits.starts_with('0') && !digits.starts_with("0.")
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_infer_cell_type() {
+        assert_eq!(ColumnType::infer(""), ColumnType::Null);
+        assert_eq!(ColumnType::infer("TRUE"), ColumnType::Boolean);
+        assert_eq!(ColumnType::infer("-12"), ColumnType::Integer);
+        assert_eq!(ColumnType::infer("0.5"), ColumnType::Float);
+        assert_eq!(ColumnType::infer("007"), ColumnType::String);
+        assert_eq!(ColumnType::infer("2024-02-29"), ColumnType::Date);
+        assert_eq!(ColumnType::infer("2024-02-30"), ColumnType::String);
+        assert_eq!(
+            ColumnType::infer("2024-02-29T10:00:00Z"),
+            ColumnType::Datetime
+        );
+        assert_eq!(ColumnType::infer("NaN"), ColumnType::String);
+    }
+
+    #[test]
+    fn test_infer_column_types() {
+        let rows = vec![
+            vec!["1", "a", "", "1", "1993-11-15"],
+            vec!["2", "b", "", "1.5", "1978-01-28 10:30:00"],
+        ];
+        let types = infer_column_types(5, rows);
+        assert_eq!(
+            types,
+            [
+                ColumnType::Integer,
+                ColumnType::String,
+                ColumnType::Null,
+                ColumnType::Float,
+                ColumnType::String
+            ]
+        );
+        assert!(ColumnType::Datetime.parse("1993-11-15").is_err());
+    }
+
+    #[test]
+    fn test_parse_typed_value() -> Result<()> {
+        assert_eq!(ColumnType::Integer.parse("42")?, Value::from(42));
+        assert_eq!(ColumnType::Boolean.parse("False")?, Value::Bool(false));
+        assert_eq!(ColumnType::Float.parse("")?, Value::Null);
+        assert_eq!(ColumnType::String.parse("")?, Value::from(""));
+        assert!(ColumnType::Integer.parse("abc").is_err());
+        Ok(())
+    }
+}
@@ -0,0 +1,319 @@
+use std::{
+    io::{Cursor, Read, Write},
+    path::Path,
+};
+
+use anyhow::{anyhow, bail, Result};
+use calamine::{open_workbook_auto_from_rs, Data, Reader};
+use chrono::{Datelike, NaiveDateTime, NaiveTime, Timelike};
+use rust_xlsxwriter::{ExcelDateTime, Format, Workbook, Worksheet};
+use serde_json::Value;
+
+use crate::{get_reader, get_writer};
+
+use super::csv_convert::{cell_to_string, flatten_records};
+use super::csv_dialect::CsvDialect;
+use super::csv_schema::{parse_date, parse_datetime, ColumnType};
+
+const SPREADSHEET_EXTENSIONS: [&str; 5] = ["xlsx", "xlsm", "xlsb", "xls", "ods"];
+/// Rows in an excel worksheet, the header included.
+const MAX_ROWS: usize = 1_048_576;
+/// Excel rejects longer sheet names.
+const MAX_SHEET_NAME: usize = 31;
+
+/// Whether the file extension says `input` is a spreadsheet.
+pub fn is_spreadsheet(input: &str) -> bool {
+    Path::new(input)
+        .extension()
+        .and_then(|ext| ext.to_str())
+        .is_some_and(|ext| {
+            SPREADSHEET_EXTENSIONS
+                .iter()
+                .any(|known| ext.eq_ignore_ascii_case(known))
+        })
+}
+
+/// Open one sheet of a spreadsheet as a csv reader, so it goes through every csv code path.
+/// The sheet is picked by name, or by its 1-based position, and is the first one by default.
+pub fn open_sheet(input: &str, dialect: &CsvDialect) -> Result<csv::Reader<Box<dyn Read>>> {
+    let mut bytes = Vec::new();
+    get_reader(input)?.read_to_end(&mut bytes)?;
+    let mut workbook = open_workbook_auto_from_rs(Cursor::new(bytes))
+        .map_err(|e| anyhow!("failed to open {} as a spreadsheet: {}", input, e))?;
+
+    let names = workbook.sheet_names();
+    let name = match dialect.sheet.as_deref() {
+        None => names.first(),
+        Some(sheet) => names.iter().find(|n| *n == sheet).or_else(|| {
+            let position = sheet.parse::<usize>().ok()?;
+            names.get(position.checked_sub(1)?)
+        }),
+    }
+    .ok_or_else(|| {
+        anyhow!(
+            "no sheet {:?} in {}, it has: {}",
+            dialect.sheet.as_deref().unwrap_or_default(),
+            input,
+            names.join(", ")
+        )
+    })?
+    .clone();
+    let range = workbook.worksheet_range(&name)?;
+
+    let mut wtr = csv::WriterBuilder::new()
+        .flexible(true)
+        .from_writer(Vec::new());
+    for row in range.rows() {
+        wtr.write_record(row.iter().map(cell_text))?;
+    }
+    let csv = wtr.into_inner().map_err(|e| e.into_error())?;
+    // the csv written above is plain utf-8 with commas whatever the dialect flags say
+    let dialect = CsvDialect {
+        has_headers: dialect.has_headers,
+        ..Default::default()
+    };
+    dialect.reader(Cursor::new(csv))
+}
+
+/// A spreadsheet cell as csv text that type inference turns back into the same value.
+fn cell_text(cell: &Data) -> String {
+    match cell {
+        Data::Empty => String::new(),
+        Data::String(s) | Data::DateTimeIso(s) | Data::DurationIso(s) => s.clone(),
+        Data::Int(i) => i.to_string(),
+        Data::Float(f) => float_text(*f),
+        Data::Bool(b) => b.to_string(),
+        Data::DateTime(dt) if dt.is_duration() => float_text(dt.as_f64()),
+        Data::DateTime(dt) => match dt.as_datetime() {
+            Some(dt) => datetime_text(dt),
+            None => float_text(dt.as_f64()),
+        },
+        Data::Error(e) => e.to_string(),
+    }
+}
+
+/// Spreadsheets store every number as a float, whole ones are written without a fraction.
+fn float_text(f: f64) -> String {
+    if f.fract() == 0.0 && f.abs() < 1e15 {
+        format!("{}", f as i64)
+    } else {
+        f.to_string()
+    }
+}
+
+fn datetime_text(dt: NaiveDateTime) -> String {
+    if dt.time() == NaiveTime::MIN {
+        dt.format("%Y-%m-%d").to_string()
+    } else {
+        dt.format("%Y-%m-%d %H:%M:%S").to_string()
+    }
+}
+
+/// Write records as an xlsx workbook with a single sheet. Numbers, booleans and dates get
+/// typed cells, so they sort and sum in excel. Strings only become dates in the columns
+/// `dates` accepts, so columns read as text stay text.
+pub fn write_xlsx(
+    records: &[Value],
+    sheet: &str,
+    output: &str,
+    dates: impl Fn(&str) -> bool,
+) -> Result<()> {
+    let (headers, rows) = flatten_records(records);
+    if rows.len() >= MAX_ROWS {
+        bail!(
+            "xlsx sheets hold at most {} rows, got {}",
+            MAX_ROWS - 1,
+            rows.len()
+        );
+    }
+
+    let mut workbook = Workbook::new();
+    let worksheet = workbook.add_worksheet();
+    worksheet.set_name(sheet.chars().take(MAX_SHEET_NAME).collect::<String>())?;
+    let bold = Format::new().set_bold();
+    let date = Format::new().set_num_format("yyyy-mm-dd");
+    let datetime = Format::new().set_num_format("yyyy-mm-dd hh:mm:ss");
+
+    for (col, header) in headers.iter().enumerate() {
+        worksheet.write_string_with_format(0, col as u16, header, &bold)?;
+    }
+    let dates = headers.iter().map(|h| dates(h)).collect::<Vec<_>>();
+    for (i, row) in rows.iter().enumerate() {
+        let row_num = i as u32 + 1;
+        for (col, header) in headers.iter().enumerate() {
+            let Some(value) = row.get(header) else {
+                continue;
+            };
+            let formats = dates[col].then_some((&date, &datetime));
+            write_cell(worksheet, row_num, col as u16, value, formats)?;
+        }
+    }
+    worksheet.set_freeze_panes(1, 0)?;
+    worksheet.autofit();
+
+    get_writer(output)?.write_all(&workbook.save_to_buffer()?)?;
+    Ok(())
+}
+
+fn write_cell(
+    worksheet: &mut Worksheet,
+    row: u32,
+    col: u16,
+    value: &Value,
+    dates: Option<(&Format, &Format)>,
+) -> Result<()> {
+    match value {
+        Value::Null => {}
+        Value::Bool(b) => {
+            worksheet.write_boolean(row, col, *b)?;
+        }
+        Value::Number(n) => {
+            worksheet.write_number(row, col, n.as_f64().unwrap_or(f64::NAN))?;
+        }
+        Value::String(s) => match dates.zip(excel_datetime(s)) {
+            Some(((date, _), (dt, false))) => {
+                worksheet.write_datetime_with_format(row, col, dt, date)?;
+            }
+            Some(((_, datetime), (dt, true))) => {
+                worksheet.write_datetime_with_format(row, col, dt, datetime)?;
+            }
+            None => {
+                worksheet.write_string(row, col, s)?;
+            }
+        },
+        v => {
+            worksheet.write_string(row, col, cell_to_string(v))?;
+        }
+    }
+    Ok(())
+}
+
+/// A date or datetime string as an excel date, and whether it has a time. Dates excel can't
+/// hold, before 1900 or after 9999, are `None` and stay strings.
+fn excel_datetime(s: &str) -> Option<(ExcelDateTime, bool)> {
+    let (dt, has_time) = match ColumnType::infer(s) {
+        ColumnType::Date => (parse_date(s)?.and_time(NaiveTime::MIN), false),
+        ColumnType::Datetime => (parse_datetime(s)?, true),
+        _ => return None,
+    };
+    let year = u16::try_from(dt.year()).ok()?;
+    let excel = ExcelDateTime::from_ymd(year, dt.month() as u8, dt.day() as u8)
+        .and_then(|d| {
+            d.and_hms(
+                dt.hour() as u16,
+                dt.minute() as u8,
+                dt.second() as f64 + f64::from(dt.nanosecond()) / 1e9,
+            )
+        })
+        .ok()?;
+    Some((excel, has_time))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{process_csv, CsvReadOpts, CsvWriteOpts, InputFormat, OutputFormat};
+    use serde_json::json;
+
+    use super::super::csv_convert::read_records;
+
+    #[test]
+    fn test_xlsx_round_trip() -> Result<()> {
+        let dir = tempfile::tempdir()?;
+        let path = dir.path().join("players.xlsx");
+        let path = path.to_str().unwrap();
+        let records = json!([
+            {"Name": "Paulo Dybala", "Kit Number": 10, "Height": 1.77, "Born": "1993-11-15", "Active": true},
+            {"Name": "Gianluigi Buffon", "Kit Number": 77, "Height": null, "Born": "1978-01-28 10:30:00", "Active": false},
+        ]);
+        write_xlsx(records.as_array().unwrap(), "players", path, |_| true)?;
+
+        let opts = CsvReadOpts {
+            infer: true,
+            ..Default::default()
+        };
+        let read = read_records(path, InputFormat::detect(path), &opts)?;
+        assert_eq!(read, records.as_array().unwrap().clone());
+        Ok(())
+    }
+
+    #[test]
+    fn test_xlsx_dates_out_of_range() -> Result<()> {
+        let dir = tempfile::tempdir()?;
+        let path = dir.path().join("history.xlsx");
+        let path = path.to_str().unwrap();
+        let records = json!([
+            {"Event": "founded", "Date": "1897-11-01"},
+            {"Event": "moved", "Date": "1933-05-20 12:00:00"},
+        ]);
+        write_xlsx(records.as_array().unwrap(), "history", path, |_| true)?;
+        let read = read_records(path, InputFormat::Xlsx, &CsvReadOpts::default())?;
+        assert_eq!(read, records.as_array().unwrap().clone());
+        Ok(())
+    }
+
+    #[test]
+    fn test_xlsx_dates_only_when_typed() -> Result<()> {
+        let dir = tempfile::tempdir()?;
+        let input = dir.path().join("history.csv");
+        std::fs::write(&input, "Event,Date\nmoved,1933-05-20\n")?;
+        for (infer, expected) in [(true, true), (false, false)] {
+            let output = dir.path().join(format!("history-{}.xlsx", infer));
+            let opts = CsvReadOpts {
+                infer,
+                ..Default::default()
+            };
+            process_csv(
+                input.to_str().unwrap(),
+                InputFormat::Csv,
+                &opts,
+                output.to_str().unwrap().to_string(),
+                OutputFormat::Xlsx,
+                &CsvWriteOpts::default(),
+            )?;
+            let mut workbook = calamine::open_workbook_auto(&output)?;
+            let range = workbook.worksheet_range_at(0).expect("a sheet")?;
+            let cell = range.get_value((1, 1)).expect("a date cell");
+            assert_eq!(matches!(cell, Data::DateTime(_)), expected, "{:?}", cell);
+        }
+        Ok(())
+    }
+
+    #[test]
+    fn test_read_ods_sheets() -> Result<()> {
+        let read = |sheet: Option<&str>| {
+            let opts = CsvReadOpts {
+                infer: true,
+                dialect: CsvDialect {
+                    sheet: sheet.map(String::from),
+                    ..Default::default()
+                },
+                ..Default::default()
+            };
+            read_records("fixtures/juventus.ods", InputFormat::Ods, &opts)
+        };
+        let players = read(None)?;
+        assert_eq!(players.len(), 3);
+        assert_eq!(
+            players[0],
+            json!({"Name": "Wojciech Szczesny", "Position": "Goalkeeper", "DOB": "1990-04-18", "Kit Number": 1, "Captain": false})
+        );
+        assert_eq!(read(Some("Staff"))?, read(Some("2"))?);
+        assert_eq!(read(Some("Staff"))?[0]["Role"], "Head Coach");
+
+        let err = read(Some("Fans")).unwrap_err();
+        assert_eq!(
+            err.to_string(),
+            "no sheet \"Fans\" in fixtures/juventus.ods, it has: Players, Staff"
+        );
+        Ok(())
+    }
+
+    #[test]
+    fn test_is_spreadsheet() {
+        assert!(is_spreadsheet("a/b.XLSX"));
+        assert!(is_spreadsheet("b.ods"));
+        assert!(!is_spreadsheet("assets/juventus.csv"));
+        assert!(!is_spreadsheet("-"));
+    }
+}
@@ -0,0 +1,205 @@
+use std::collections::VecDeque;
+
+use anyhow::{anyhow, Result};
+use unicode_width::{UnicodeWidthChar, UnicodeWidthStr};
+
+use super::csv_convert::open_csv;
+use super::csv_dialect::CsvDialect;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum TableStyle {
+    /// box drawing borders, for terminals
+    Unicode,
+    /// space separated columns, for pipes
+    Plain,
+}
+
+/// Render the selected rows and columns of a csv file as a table.
+pub fn process_csv_show(
+    input: &str,
+    dialect: &CsvDialect,
+    head: Option<usize>,
+    tail: Option<usize>,
+    columns: &[String],
+    max_width: usize,
+    style: TableStyle,
+) -> Result<String> {
+    let mut rdr = open_csv(input, dialect)?;
+    let headers = rdr.headers()?.clone();
+
+    let indices = if columns.is_empty() {
+        (0..headers.len()).collect::<Vec<_>>()
+    } else {
+        columns
+            .iter()
+            .map(|c| {
+                headers
+                    .iter()
+                    .position(|h| h == c)
+                    .ok_or_else(|| anyhow!("column {:?} not found", c))
+            })
+            .collect::<Result<_>>()?
+    };
+    let select = |record: &csv::StringRecord| {
+        indices
+            .iter()
+            .map(|&i| record.get(i).unwrap_or_default().to_string())
+            .collect::<Vec<_>>()
+    };
+
+    let mut rows = VecDeque::new();
+    for record in rdr.records() {
+        let record = record?;
+        if head.is_some_and(|n| rows.len() >= n) {
+            break;
+        }
+        rows.push_back(select(&record));
+        // only the last n rows are kept, so the whole file never sits in memory
+        if tail.is_some_and(|n| rows.len() > n) {
+            rows.pop_front();
+        }
+    }
+
+    let headers = select(&headers);
+    Ok(render_table(
+        &headers,
+        rows.make_contiguous(),
+        max_width,
+        style,
+    ))
+}
+
+/// Render rows as a column aligned table, truncating cells wider than `max_width`.
+pub fn render_table(
+    headers: &[String],
+    rows: &[Vec<String>],
+    max_width: usize,
+    style: TableStyle,
+) -> String {
+    let headers = headers
+        .iter()
+        .map(|h| truncate(h, max_width))
+        .collect::<Vec<_>>();
+    let rows = rows
+        .iter()
+        .map(|row| row.iter().map(|c| truncate(c, max_width)).collect())
+        .collect::<Vec<Vec<_>>>();
+
+    let mut widths = headers.iter().map(|h| h.width()).collect::<Vec<_>>();
+    for row in &rows {
+        for (w, cell) in widths.iter_mut().zip(row) {
+            *w = (*w).max(cell.width());
+        }
+    }
+
+    let mut ret = String::new();
+    match style {
+        TableStyle::Unicode => {
+            ret.push_str(&border(&widths, '┌', '┬', '┐'));
+            ret.push_str(&line(&headers, &widths, "│ ", " │ ", " │"));
+            ret.push_str(&border(&widths, '├', '┼', '┤'));
+            for row in &rows {
+                ret.push_str(&line(row, &widths, "│ ", " │ ", " │"));
+            }
+            ret.push_str(&border(&widths, '└', '┴', '┘'));
+        }
+        TableStyle::Plain => {
+            ret.push_str(line(&headers, &widths, "", "  ", "").trim_end());
+            ret.push('\n');
+            for row in &rows {
+                ret.push_str(line(row, &widths, "", "  ", "").trim_end());
+                ret.push('\n');
+            }
+        }
+    }
+    ret
+}
+
+fn border(widths: &[usize], left: char, mid: char, right: char) -> String {
+    let cells = widths
+        .iter()
+        .map(|w| "─".repeat(w + 2))
+        .collect::<Vec<_>>()
+        .join(&mid.to_string());
+    format!("{}{}{}\n", left, cells, right)
+}
+
+fn line(cells: &[String], widths: &[usize], left: &str, mid: &str, right: &str) -> String {
+    let cells = cells
+        .iter()
+        .zip(widths)
+        .map(|(c, w)| format!("{}{}", c, " ".repeat(w - c.width())))
+        .collect::<Vec<_>>()
+        .join(mid);
+    format!("{}{}{}\n", left, cells, right)
+}
+
+/// Cut a cell down to `max` display columns, marking the cut with an ellipsis.
+/// Line breaks are flattened so that every row stays on one line.
+fn truncate(s: &str, max: usize) -> String {
+    let s = s.replace(['\r', '\n'], " ");
+    if max == 0 || s.width() <= max {
+        return s;
+    }
+    let mut ret = String::new();
+    let mut width = 0;
+    for c in s.chars() {
+        let w = c.width().unwrap_or(0);
+        if width + w > max - 1 {
+            break;
+        }
+        width += w;
+        ret.push(c);
+    }
+    ret.push('…');
+    ret
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_truncate_cell() {
+        assert_eq!(truncate("hello", 10), "hello");
+        assert_eq!(truncate("hello world", 6), "hello…");
+        assert_eq!(truncate("中文字符", 5), "中文…");
+        assert_eq!(truncate("a\nb", 0), "a b");
+    }
+
+    #[test]
+    fn test_render_unicode_table() {
+        let headers = vec!["a".to_string(), "bb".to_string()];
+        let rows = vec![vec!["1".to_string(), "中".to_string()]];
+        let table = render_table(&headers, &rows, 0, TableStyle::Unicode);
+        assert_eq!(
+            table,
+            "┌───┬────┐\n│ a │ bb │\n├───┼────┤\n│ 1 │ 中 │\n└───┴────┘\n"
+        );
+        let table = render_table(&headers, &rows, 0, TableStyle::Plain);
+        assert_eq!(table, "a  bb\n1  中\n");
+    }
+
+    #[test]
+    fn test_show_head_tail_columns() -> Result<()> {
+        let columns = vec!["Kit Number".to_string(), "Name".to_string()];
+        let input = "assets/juventus.csv";
+        let dialect = CsvDialect::default();
+        let table = process_csv_show(
+            input,
+            &dialect,
+            None,
+            Some(2),
+            &columns,
+            0,
+            TableStyle::Plain,
+        )?;
+        let lines = table.lines().collect::<Vec<_>>();
+        assert_eq!(lines.len(), 3);
+        assert!(lines[0].starts_with("Kit Number  Name"));
+
+        let table = process_csv_show(input, &dialect, Some(1), None, &[], 0, TableStyle::Plain)?;
+        assert_eq!(table.lines().count(), 2);
+        Ok(())
+    }
+}
@@ -0,0 +1,385 @@
+use std::{
+    cmp::Ordering,
+    fs::File,
+    io::{BufReader, Seek, Write},
+    path::Path,
+};
+
+use anyhow::{anyhow, Result};
+use csv::StringRecord;
+
+use super::csv_convert::open_csv_dialect;
+use super::csv_dialect::CsvDialect;
+
+/// How many runs are merged at once, more are merged in several passes.
+const MERGE_WIDTH: usize = 64;
+/// What a record costs in memory on top of its bytes, so tiny records still add up.
+const RECORD_OVERHEAD: usize = 64;
+
+/// How to sort a csv file.
+#[derive(Debug)]
+pub struct SortSpec<'a> {
+    /// columns to sort by, the whole row if empty
+    pub by: &'a [String],
+    /// compare the key columns as numbers, with non-numbers after them
+    pub numeric: bool,
+    pub desc: bool,
+    /// keep only the first row of every key
+    pub unique: bool,
+    /// bytes of rows to sort in memory before spilling them to a temporary file
+    pub buffer_size: usize,
+    /// where to create the temporary files, the system default if omitted
+    pub temp_dir: Option<&'a Path>,
+}
+
+/// Sort a csv file of any size into `output`, returning the number of rows written. Rows are
+/// sorted in memory in chunks of `buffer_size`, spilled to temporary files and merged. The
+/// sort is stable, so `unique` keeps the first row of every key in input order.
+pub fn process_csv_sort(
+    input: &str,
+    dialect: &CsvDialect,
+    spec: &SortSpec,
+    output: impl Write,
+) -> Result<usize> {
+    sort_with(input, dialect, spec, output, MERGE_WIDTH)
+}
+
+fn sort_with(
+    input: &str,
+    dialect: &CsvDialect,
+    spec: &SortSpec,
+    output: impl Write,
+    merge_width: usize,
+) -> Result<usize> {
+    let (mut rdr, dialect) = open_csv_dialect(input, dialect)?;
+    let headers = rdr.headers()?.clone();
+    let keys = match spec.by.is_empty() {
+        true => (0..headers.len()).collect(),
+        false => spec
+            .by
+            .iter()
+            .map(|name| {
+                headers
+                    .iter()
+                    .position(|h| h == name)
+                    .ok_or_else(|| anyhow!("column {:?} not found in {}", name, input))
+            })
+            .collect::<Result<Vec<_>>>()?,
+    };
+    let order = KeyOrder {
+        keys,
+        numeric: spec.numeric,
+        desc: spec.desc,
+    };
+
+    let mut runs = Runs {
+        levels: Vec::new(),
+        order: &order,
+        unique: spec.unique,
+        width: merge_width,
+        temp_dir: spec.temp_dir,
+    };
+    let mut chunk = Vec::new();
+    let mut size = 0;
+    for record in rdr.records() {
+        let record = record?;
+        size += record.as_byte_record().as_slice().len() + RECORD_OVERHEAD;
+        chunk.push(record);
+        if size >= spec.buffer_size {
+            let sorted = order.sort(std::mem::take(&mut chunk), spec.unique);
+            runs.push(spill(sorted.iter(), spec.temp_dir)?)?;
+            size = 0;
+        }
+    }
+    let chunk = order.sort(chunk, spec.unique);
+
+    let mut wtr = dialect.writer(output);
+    // the generated column names of headerless input aren't part of it
+    if dialect.has_headers {
+        wtr.write_record(&headers)?;
+    }
+    let mut written = 0;
+    if runs.levels.is_empty() {
+        for record in &chunk {
+            wtr.write_record(record)?;
+        }
+        written = chunk.len();
+    } else {
+        if !chunk.is_empty() {
+            runs.push(spill(chunk.iter(), spec.temp_dir)?)?;
+        }
+        merge(runs.finish()?, &order, spec.unique, |record| {
+            written += 1;
+            Ok(wtr.write_record(record)?)
+        })?;
+    }
+    wtr.flush()?;
+    Ok(written)
+}
+
+/// Sorted runs spilled to temporary files. Whenever `width` runs pile up on a level they are
+/// merged into one run on the next level, so only a few files per level are open at a time.
+struct Runs<'a> {
+    /// runs of the same level are in input order, and higher levels hold earlier rows
+    levels: Vec<Vec<File>>,
+    order: &'a KeyOrder,
+    unique: bool,
+    width: usize,
+    temp_dir: Option<&'a Path>,
+}
+
+impl Runs<'_> {
+    fn push(&mut self, mut run: File) -> Result<()> {
+        for level in 0.. {
+            if self.levels.len() == level {
+                self.levels.push(Vec::new());
+            }
+            self.levels[level].push(run);
+            if self.levels[level].len() < self.width {
+                break;
+            }
+            let full = std::mem::take(&mut self.levels[level]);
+            run = self.merge(full)?;
+        }
+        Ok(())
+    }
+
+    /// The runs left, at most `width` of them, earliest rows first.
+    fn finish(mut self) -> Result<Vec<File>> {
+        let mut runs = std::mem::take(&mut self.levels)
+            .into_iter()
+            .rev()
+            .flatten()
+            .collect::<Vec<_>>();
+        while runs.len() > self.width {
+            let mut merged = Vec::new();
+            // merging neighbouring runs keeps earlier rows in earlier runs, which the merge
+            // relies on to stay stable
+            while !runs.is_empty() {
+                let group = runs.drain(..self.width.min(runs.len())).collect();
+                merged.push(self.merge(group)?);
+            }
+            runs = merged;
+        }
+        Ok(runs)
+    }
+
+    fn merge(&self, runs: Vec<File>) -> Result<File> {
+        let mut wtr = run_writer(self.temp_dir)?;
+        merge(runs, self.order, self.unique, |r| Ok(wtr.write_record(r)?))?;
+        finish_run(wtr)
+    }
+}
+
+/// Compares records by their key columns.
+#[derive(Debug)]
+struct KeyOrder {
+    keys: Vec<usize>,
+    numeric: bool,
+    desc: bool,
+}
+
+impl KeyOrder {
+    fn cmp(&self, a: &StringRecord, b: &StringRecord) -> Ordering {
+        let order = self
+            .keys
+            .iter()
+            .map(|i| self.cmp_cell(a.get(*i).unwrap_or_default(), b.get(*i).unwrap_or_default()))
+            .find(|o| o.is_ne())
+            .unwrap_or(Ordering::Equal);
+        if self.desc {
+            order.reverse()
+        } else {
+            order
+        }
+    }
+
+    fn cmp_cell(&self, a: &str, b: &str) -> Ordering {
+        if !self.numeric {
+            return a.cmp(b);
+        }
+        let number = |s: &str| s.trim().parse::<f64>().ok().filter(|n| !n.is_nan());
+        match (number(a), number(b)) {
+            (Some(x), Some(y)) => x.total_cmp(&y),
+            (Some(_), None) => Ordering::Less,
+            (None, Some(_)) => Ordering::Greater,
+            (None, None) => a.cmp(b),
+        }
+    }
+
+    fn sort(&self, mut records: Vec<StringRecord>, unique: bool) -> Vec<StringRecord> {
+        records.sort_by(|a, b| self.cmp(a, b));
+        if unique {
+            records.dedup_by(|a, b| self.cmp(a, b).is_eq());
+        }
+        records
+    }
+}
+
+fn run_writer(temp_dir: Option<&Path>) -> Result<csv::Writer<File>> {
+    let file = match temp_dir {
+        Some(dir) => tempfile::tempfile_in(dir)?,
+        None => tempfile::tempfile()?,
+    };
+    Ok(csv::WriterBuilder::new().flexible(true).from_writer(file))
+}
+
+fn finish_run(wtr: csv::Writer<File>) -> Result<File> {
+    let mut file = wtr.into_inner().map_err(|e| e.into_error())?;
+    file.rewind()?;
+    Ok(file)
+}
+
+/// Write sorted records to an anonymous temporary file, deleted once it's dropped.
+fn spill<'a>(
+    records: impl Iterator<Item = &'a StringRecord>,
+    temp_dir: Option<&Path>,
+) -> Result<File> {
+    let mut wtr = run_writer(temp_dir)?;
+    for record in records {
+        wtr.write_record(record)?;
+    }
+    finish_run(wtr)
+}
+
+/// Merge sorted runs, taking the earliest run on ties.
+fn merge(
+    runs: Vec<File>,
+    order: &KeyOrder,
+    unique: bool,
+    mut emit: impl FnMut(&StringRecord) -> Result<()>,
+) -> Result<()> {
+    let mut readers = runs
+        .into_iter()
+        .map(|file| {
+            csv::ReaderBuilder::new()
+                .has_headers(false)
+                .flexible(true)
+                .from_reader(BufReader::new(file))
+        })
+        .collect::<Vec<_>>();
+    let mut heads = readers
+        .iter_mut()
+        .map(next_record)
+        .collect::<Result<Vec<_>>>()?;
+
+    let mut last: Option<StringRecord> = None;
+    loop {
+        let mut best: Option<(usize, &StringRecord)> = None;
+        for (i, head) in heads.iter().enumerate() {
+            if let Some(record) = head {
+                if best.is_none_or(|(_, b)| order.cmp(record, b).is_lt()) {
+                    best = Some((i, record));
+                }
+            }
+        }
+        let Some((i, _)) = best else {
+            return Ok(());
+        };
+        let next = next_record(&mut readers[i])?;
+        let Some(record) = std::mem::replace(&mut heads[i], next) else {
+            continue;
+        };
+        if unique && last.as_ref().is_some_and(|l| order.cmp(l, &record).is_eq()) {
+            continue;
+        }
+        emit(&record)?;
+        last = Some(record);
+    }
+}
+
+fn next_record(rdr: &mut csv::Reader<BufReader<File>>) -> Result<Option<StringRecord>> {
+    let mut record = StringRecord::new();
+    Ok(rdr.read_record(&mut record)?.then_some(record))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn sort(by: &[&str], numeric: bool, desc: bool, unique: bool, buffer_size: usize) -> String {
+        let by = by.iter().map(|s| s.to_string()).collect::<Vec<_>>();
+        let spec = SortSpec {
+            by: &by,
+            numeric,
+            desc,
+            unique,
+            buffer_size,
+            temp_dir: None,
+        };
+        let mut out = Vec::new();
+        // a merge width of 2 forces several merge passes over the tiny runs
+        sort_with(
+            "assets/juventus.csv",
+            &CsvDialect::default(),
+            &spec,
+            &mut out,
+            2,
+        )
+        .unwrap();
+        String::from_utf8(out).unwrap()
+    }
+
+    fn column(csv: &str, i: usize) -> Vec<String> {
+        let mut rdr = csv::Reader::from_reader(csv.as_bytes());
+        rdr.records().map(|r| r.unwrap()[i].to_string()).collect()
+    }
+
+    #[test]
+    fn test_external_sort_matches_in_memory() {
+        for (by, numeric, desc) in [
+            (&["Kit Number"][..], true, false),
+            (&["Position", "Name"][..], false, true),
+            (&[][..], false, false),
+        ] {
+            let in_memory = sort(by, numeric, desc, false, usize::MAX);
+            assert_eq!(sort(by, numeric, desc, false, 1), in_memory);
+            assert_eq!(sort(by, numeric, desc, false, 300), in_memory);
+        }
+
+        let kits = column(&sort(&["Kit Number"], true, true, false, 300), 4);
+        assert_eq!(kits[..3], ["77", "37", "33"]);
+        assert_eq!(kits.len(), 27);
+        // compared as text 10 comes before 2
+        let kits = column(&sort(&["Kit Number"], false, false, false, 300), 4);
+        assert_eq!(kits[..3], ["1", "10", "11"]);
+    }
+
+    #[test]
+    fn test_dedup_keeps_first_row() {
+        for buffer_size in [1, 300, usize::MAX] {
+            let out = sort(&["Nationality"], false, false, true, buffer_size);
+            let names = column(&out, 0);
+            assert_eq!(names.len(), 14);
+            assert_eq!(column(&out, 3)[9], "Poland");
+            assert_eq!(names[9], "Wojciech Szczesny");
+            assert_eq!(column(&out, 3)[7], "Italy");
+            assert_eq!(names[7], "Mattia Perin");
+        }
+    }
+
+    #[test]
+    fn test_sort_keeps_dialect() -> Result<()> {
+        let dir = tempfile::tempdir()?;
+        let path = dir.path().join("kits.csv");
+        std::fs::write(&path, "b;2\na;10\nc;1\n")?;
+        let by = vec!["column2".to_string()];
+        let spec = SortSpec {
+            by: &by,
+            numeric: true,
+            desc: false,
+            unique: false,
+            buffer_size: 1,
+            temp_dir: None,
+        };
+        let dialect = CsvDialect {
+            delimiter: b';',
+            has_headers: false,
+            ..Default::default()
+        };
+        let mut out = Vec::new();
+        sort_with(path.to_str().unwrap(), &dialect, &spec, &mut out, 2)?;
+        assert_eq!(String::from_utf8(out)?, "c;1\nb;2\na;10\n");
+        Ok(())
+    }
+}
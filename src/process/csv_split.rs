@@ -0,0 +1,379 @@
+use std::{
+    collections::HashMap,
+    fs::{self, File, OpenOptions},
+    io::{BufWriter, ErrorKind},
+    path::{Path, PathBuf},
+};
+
+use super::csv_convert::open_csv_dialect;
+use super::csv_dialect::CsvDialect;
+use anyhow::{anyhow, bail, Result};
+
+/// Chunk files kept open at once when splitting by column, well below the usual limits on
+/// open files.
+const MAX_OPEN_FILES: usize = 64;
+
+/// How to partition the rows of a csv file.
+#[derive(Debug, Clone, Copy)]
+pub enum SplitBy<'a> {
+    /// chunks of at most this many rows
+    Rows(usize),
+    /// one file per distinct value of a column
+    Column(&'a str),
+}
+
+impl SplitBy<'_> {
+    /// `{stem}` is the input file name without its extension, `{n}` the 1-based chunk number
+    /// and `{value}` the column value the file holds.
+    pub fn default_template(&self) -> &'static str {
+        match self {
+            Self::Rows(_) => "{stem}-{n}.csv",
+            Self::Column(_) => "{stem}-{value}.csv",
+        }
+    }
+}
+
+/// A file written by a split.
+#[derive(Debug)]
+pub struct SplitChunk {
+    pub path: PathBuf,
+    pub rows: usize,
+}
+
+type ChunkWriter = csv::Writer<BufWriter<File>>;
+
+/// Split a csv file into files named after `template` inside `dir`, each starting with the
+/// header if the input has one, and laid out like the input. Files are created in the order
+/// their first row appears, and never over existing ones.
+pub fn process_csv_split(
+    input: &str,
+    dialect: &CsvDialect,
+    split: SplitBy,
+    template: &str,
+    dir: &Path,
+) -> Result<Vec<SplitChunk>> {
+    split_with(input, dialect, split, template, dir, MAX_OPEN_FILES)
+}
+
+fn split_with(
+    input: &str,
+    dialect: &CsvDialect,
+    split: SplitBy,
+    template: &str,
+    dir: &Path,
+    max_open: usize,
+) -> Result<Vec<SplitChunk>> {
+    match split {
+        SplitBy::Rows(0) => bail!("chunks need at least one row"),
+        SplitBy::Rows(_) if !template.contains("{n}") => {
+            bail!("the file name template needs {{n}} to tell the chunks apart")
+        }
+        SplitBy::Column(_) if !template.contains("{n}") && !template.contains("{value}") => {
+            bail!("the file name template needs {{value}} or {{n}} to tell the chunks apart")
+        }
+        _ => {}
+    }
+    let (mut rdr, dialect) = open_csv_dialect(input, dialect)?;
+    let headers = rdr.headers()?.clone();
+    let column = match split {
+        SplitBy::Column(name) => headers
+            .iter()
+            .position(|h| h == name)
+            .ok_or_else(|| anyhow!("column {:?} not found in {}", name, input)),
+        SplitBy::Rows(_) => Ok(0),
+    }?;
+    let stem = Path::new(input)
+        .file_stem()
+        .and_then(|s| s.to_str())
+        .filter(|_| input != "-")
+        .unwrap_or("stdin");
+
+    let mut chunks: Vec<SplitChunk> = Vec::new();
+    let mut files = ChunkFiles {
+        dialect: &dialect,
+        headers: &headers,
+        open: HashMap::new(),
+        max_open,
+        writes: 0,
+    };
+    let mut values: HashMap<String, usize> = HashMap::new();
+    // the value written to each path, lowercased as some file systems ignore case
+    let mut paths: HashMap<String, String> = HashMap::new();
+    let mut record = csv::StringRecord::new();
+    while rdr.read_record(&mut record)? {
+        let chunk = match split {
+            SplitBy::Rows(size) => match chunks.last() {
+                Some(last) if last.rows < size => chunks.len() - 1,
+                _ => {
+                    // only one chunk is written at a time
+                    if !chunks.is_empty() {
+                        files.close(chunks.len() - 1)?;
+                    }
+                    let path = dir.join(render_template(template, stem, chunks.len() + 1, ""));
+                    files.create(chunks.len(), &path)?;
+                    chunks.push(SplitChunk { path, rows: 0 });
+                    chunks.len() - 1
+                }
+            },
+            SplitBy::Column(_) => {
+                let value = record.get(column).unwrap_or_default();
+                match values.get(value) {
+                    Some(chunk) => *chunk,
+                    None => {
+                        let n = chunks.len() + 1;
+                        let path = dir.join(render_template(template, stem, n, value));
+                        let key = path.to_string_lossy().to_lowercase();
+                        if let Some(other) = paths.get(&key) {
+                            bail!(
+                                "{:?} and {:?} would both be written to {}, use {{n}} in the template",
+                                other,
+                                value,
+                                path.display()
+                            );
+                        }
+                        files.create(n - 1, &path)?;
+                        chunks.push(SplitChunk { path, rows: 0 });
+                        values.insert(value.to_string(), n - 1);
+                        paths.insert(key, value.to_string());
+                        n - 1
+                    }
+                }
+            }
+        };
+        files.write(chunk, &chunks[chunk].path, &record)?;
+        chunks[chunk].rows += 1;
+    }
+    files.finish()?;
+    Ok(chunks)
+}
+
+/// The chunk files being written. When too many are open the least recently written one is
+/// closed, and reopened in append mode when it gets another row.
+struct ChunkFiles<'a> {
+    dialect: &'a CsvDialect,
+    headers: &'a csv::StringRecord,
+    /// open writers by chunk, with the write count when they were last written to
+    open: HashMap<usize, (ChunkWriter, u64)>,
+    max_open: usize,
+    writes: u64,
+}
+
+impl ChunkFiles<'_> {
+    /// Create the file of a new chunk, starting with the header. Existing files, the input
+    /// or an earlier split among them, are left alone.
+    fn create(&mut self, chunk: usize, path: &Path) -> Result<()> {
+        if let Some(parent) = path.parent() {
+            fs::create_dir_all(parent)?;
+        }
+        let file = OpenOptions::new()
+            .write(true)
+            .create_new(true)
+            .open(path)
+            .map_err(|e| match e.kind() {
+                ErrorKind::AlreadyExists => anyhow!("{} already exists", path.display()),
+                _ => anyhow!("failed to create {}: {}", path.display(), e),
+            })?;
+        let mut wtr = self.dialect.writer(BufWriter::new(file));
+        // the generated column names of headerless input aren't part of it
+        if self.dialect.has_headers {
+            wtr.write_record(self.headers)?;
+        }
+        self.insert(chunk, wtr)
+    }
+
+    fn write(&mut self, chunk: usize, path: &Path, record: &csv::StringRecord) -> Result<()> {
+        if !self.open.contains_key(&chunk) {
+            let file = OpenOptions::new()
+                .append(true)
+                .open(path)
+                .map_err(|e| anyhow!("failed to reopen {}: {}", path.display(), e))?;
+            let wtr = self.dialect.writer(BufWriter::new(file));
+            self.insert(chunk, wtr)?;
+        }
+        self.writes += 1;
+        let (wtr, last) = self.open.get_mut(&chunk).expect("opened above");
+        *last = self.writes;
+        wtr.write_record(record)?;
+        Ok(())
+    }
+
+    fn insert(&mut self, chunk: usize, wtr: ChunkWriter) -> Result<()> {
+        if self.open.len() >= self.max_open {
+            let oldest = self
+                .open
+                .iter()
+                .min_by_key(|(_, (_, last))| *last)
+                .map(|(chunk, _)| *chunk);
+            if let Some(oldest) = oldest {
+                self.close(oldest)?;
+            }
+        }
+        self.open.insert(chunk, (wtr, self.writes));
+        Ok(())
+    }
+
+    fn close(&mut self, chunk: usize) -> Result<()> {
+        if let Some((mut wtr, _)) = self.open.remove(&chunk) {
+            wtr.flush()?;
+        }
+        Ok(())
+    }
+
+    fn finish(self) -> Result<()> {
+        for (mut wtr, _) in self.open.into_values() {
+            wtr.flush()?;
+        }
+        Ok(())
+    }
+}
+
+fn render_template(template: &str, stem: &str, n: usize, value: &str) -> String {
+    template
+        .replace("{stem}", stem)
+        .replace("{n}", &n.to_string())
+        .replace("{value}", &file_name_safe(value))
+}
+
+/// Keep a column value usable as (part of) a file name on any platform.
+fn file_name_safe(value: &str) -> String {
+    let name = value
+        .trim()
+        .chars()
+        .map(|c| match c {
+            c if c.is_alphanumeric() => c,
+            '-' | '_' | '.' | ' ' => c,
+            _ => '_',
+        })
+        .collect::<String>();
+    match name.trim_start_matches('.') {
+        "" => "_".to_string(),
+        _ => name,
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn read(path: &Path) -> Vec<String> {
+        fs::read_to_string(path)
+            .unwrap()
+            .lines()
+            .map(String::from)
+            .collect()
+    }
+
+    #[test]
+    fn test_split_by_rows() -> Result<()> {
+        let dir = tempfile::tempdir()?;
+        let chunks = process_csv_split(
+            "assets/juventus.csv",
+            &CsvDialect::default(),
+            SplitBy::Rows(10),
+            "part-{n}.csv",
+            dir.path(),
+        )?;
+        assert_eq!(
+            chunks.iter().map(|c| c.rows).collect::<Vec<_>>(),
+            [10, 10, 7]
+        );
+        assert_eq!(chunks[2].path, dir.path().join("part-3.csv"));
+        let lines = read(&chunks[2].path);
+        assert_eq!(lines[0], "Name,Position,DOB,Nationality,Kit Number");
+        assert_eq!(lines.len(), 8);
+        Ok(())
+    }
+
+    #[test]
+    fn test_split_by_column() -> Result<()> {
+        let dir = tempfile::tempdir()?;
+        let split = SplitBy::Column("Position");
+        let chunks = process_csv_split(
+            "assets/juventus.csv",
+            &CsvDialect::default(),
+            split,
+            split.default_template(),
+            dir.path(),
+        )?;
+        assert_eq!(chunks.len(), 10);
+        assert_eq!(chunks[0].path, dir.path().join("juventus-Goalkeeper.csv"));
+        assert_eq!(chunks[0].rows, 4);
+        assert_eq!(chunks.iter().map(|c| c.rows).sum::<usize>(), 27);
+        let lines = read(&chunks[0].path);
+        assert_eq!(lines[0], "Name,Position,DOB,Nationality,Kit Number");
+        assert!(lines[1..].iter().all(|l| l.contains(",Goalkeeper,")));
+
+        let err = process_csv_split(
+            "assets/juventus.csv",
+            &CsvDialect::default(),
+            split,
+            "all.csv",
+            dir.path(),
+        )
+        .unwrap_err();
+        assert!(err.to_string().contains("needs {value} or {n}"));
+        Ok(())
+    }
+
+    #[test]
+    fn test_split_reopens_files() -> Result<()> {
+        let dir = tempfile::tempdir()?;
+        let input = dir.path().join("kits.csv");
+        fs::write(&input, "a;x\nb;y\nc;z\nd;x\ne;y\nf;\"z;\"\n")?;
+        let dialect = CsvDialect {
+            delimiter: b';',
+            has_headers: false,
+            ..Default::default()
+        };
+        // two open files for three values closes and reopens them in turn
+        let chunks = split_with(
+            input.to_str().unwrap(),
+            &dialect,
+            SplitBy::Column("column2"),
+            "{value}.csv",
+            dir.path(),
+            2,
+        )?;
+        assert_eq!(
+            chunks.iter().map(|c| c.rows).collect::<Vec<_>>(),
+            [2, 2, 1, 1]
+        );
+        assert_eq!(read(&dir.path().join("x.csv")), ["a;x", "d;x"]);
+        assert_eq!(read(&dir.path().join("y.csv")), ["b;y", "e;y"]);
+        assert_eq!(read(&dir.path().join("z_.csv")), ["f;\"z;\""]);
+        Ok(())
+    }
+
+    #[test]
+    fn test_split_keeps_existing_files() -> Result<()> {
+        let dir = tempfile::tempdir()?;
+        let split = |input: &str, split, template| {
+            process_csv_split(input, &CsvDialect::default(), split, template, dir.path())
+        };
+        split("assets/juventus.csv", SplitBy::Rows(10), "part-{n}.csv")?;
+        let err = split("assets/juventus.csv", SplitBy::Rows(20), "part-{n}.csv").unwrap_err();
+        assert!(err.to_string().ends_with("part-1.csv already exists"));
+        assert_eq!(read(&dir.path().join("part-2.csv")).len(), 11);
+
+        let input = dir.path().join("roles.csv");
+        fs::write(&input, "role\nGoalkeeper\ngoalkeeper\n")?;
+        let err = split(
+            input.to_str().unwrap(),
+            SplitBy::Column("role"),
+            "{value}.csv",
+        )
+        .unwrap_err();
+        assert!(err
+            .to_string()
+            .starts_with("\"Goalkeeper\" and \"goalkeeper\" would both be written to"));
+        Ok(())
+    }
+
+    #[test]
+    fn test_file_name_safe() {
+        assert_eq!(file_name_safe("Centre-Back"), "Centre-Back");
+        assert_eq!(file_name_safe("a/b\\c:d"), "a_b_c_d");
+        assert_eq!(file_name_safe(".."), "_");
+        assert_eq!(file_name_safe(""), "_");
+    }
+}
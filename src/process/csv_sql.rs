@@ -0,0 +1,261 @@
+use std::{fmt::Write, path::Path};
+
+use anyhow::{anyhow, bail, Result};
+use rusqlite::{params_from_iter, types::Value as SqlValue, Connection};
+use serde_json::{Map, Value};
+
+use super::csv_convert::flatten_records;
+use super::csv_schema::ColumnType;
+
+/// The table name used when there's no input file to name it after.
+pub const DEFAULT_TABLE: &str = "data";
+
+/// A table name derived from an input path: its file stem with anything but letters, digits
+/// and underscores replaced.
+pub fn table_name(input: &str) -> String {
+    let stem = Path::new(input)
+        .file_stem()
+        .and_then(|s| s.to_str())
+        .filter(|_| input != "-")
+        .unwrap_or(DEFAULT_TABLE);
+    let name = stem
+        .chars()
+        .map(|c| if c.is_alphanumeric() { c } else { '_' })
+        .collect::<String>();
+    if name.starts_with(|c: char| c.is_ascii_digit()) {
+        format!("_{}", name)
+    } else {
+        name
+    }
+}
+
+/// Render records as a portable sql script: a `CREATE TABLE` with column types from the
+/// values, then one `INSERT` per record inside a transaction.
+pub fn render_sql(records: &[Value], table: &str) -> Result<String> {
+    let (headers, rows) = flatten_records(records);
+    let types = column_types(&headers, &rows);
+    let columns = headers
+        .iter()
+        .map(|h| quote_ident(h))
+        .collect::<Vec<_>>()
+        .join(", ");
+
+    let mut out = create_table(table, &headers, &types)?;
+    out.push_str("BEGIN;\n");
+    for row in &rows {
+        let values = headers
+            .iter()
+            .map(|h| sql_literal(row.get(h).unwrap_or(&Value::Null)))
+            .collect::<Vec<_>>()
+            .join(", ");
+        writeln!(
+            out,
+            "INSERT INTO {} ({}) VALUES ({});",
+            quote_ident(table),
+            columns,
+            values
+        )?;
+    }
+    out.push_str("COMMIT;\n");
+    Ok(out)
+}
+
+/// Write records into a new sqlite database file. A file already at `path` is only replaced
+/// with `force`, and only once the new database is complete.
+pub fn write_sqlite(records: &[Value], table: &str, path: &str, force: bool) -> Result<()> {
+    if path == "-" {
+        bail!("sqlite output must be written to a file, not stdout");
+    }
+    let target = Path::new(path);
+    if target.exists() && !force {
+        bail!("{} already exists, use --force to replace it", path);
+    }
+    let (headers, rows) = flatten_records(records);
+    let types = column_types(&headers, &rows);
+    let create = create_table(table, &headers, &types)?;
+
+    // built next to the target, so it can be renamed over it
+    let dir = match target.parent() {
+        Some(dir) if !dir.as_os_str().is_empty() => dir,
+        _ => Path::new("."),
+    };
+    let file = tempfile::NamedTempFile::new_in(dir)?;
+    fill_sqlite(file.path(), table, &create, &headers, &rows)?;
+    let persisted = match force {
+        true => file.persist(target),
+        false => file.persist_noclobber(target),
+    };
+    persisted.map_err(|e| anyhow!("failed to write {}: {}", path, e.error))?;
+    Ok(())
+}
+
+fn fill_sqlite(
+    path: &Path,
+    table: &str,
+    create: &str,
+    headers: &[String],
+    rows: &[Map<String, Value>],
+) -> Result<()> {
+    let mut conn = Connection::open(path)?;
+    conn.execute_batch(create)?;
+    let tx = conn.transaction()?;
+    {
+        let placeholders = vec!["?"; headers.len()].join(", ");
+        let mut stmt = tx.prepare(&format!(
+            "INSERT INTO {} VALUES ({})",
+            quote_ident(table),
+            placeholders
+        ))?;
+        for row in rows {
+            stmt.execute(params_from_iter(
+                headers
+                    .iter()
+                    .map(|h| sql_value(row.get(h).unwrap_or(&Value::Null))),
+            ))?;
+        }
+    }
+    tx.commit()?;
+    Ok(())
+}
+
+fn create_table(table: &str, headers: &[String], types: &[ColumnType]) -> Result<String> {
+    if headers.is_empty() {
+        bail!("no columns to create table {} with", table);
+    }
+    let columns = headers
+        .iter()
+        .zip(types)
+        .map(|(h, t)| format!("  {} {}", quote_ident(h), sql_type(*t)))
+        .collect::<Vec<_>>()
+        .join(",\n");
+    Ok(format!(
+        "CREATE TABLE {} (\n{}\n);\n",
+        quote_ident(table),
+        columns
+    ))
+}
+
+/// The type of every column, merged over its values. Strings only keep a type of their own
+/// when they hold dates, so text that merely looks numeric stays text.
+fn column_types(headers: &[String], rows: &[Map<String, Value>]) -> Vec<ColumnType> {
+    headers
+        .iter()
+        .map(|h| {
+            rows.iter()
+                .filter_map(|row| row.get(h))
+                .map(|v| match v {
+                    Value::Null => ColumnType::Null,
+                    Value::Bool(_) => ColumnType::Boolean,
+                    Value::Number(n) if n.is_f64() => ColumnType::Float,
+                    Value::Number(_) => ColumnType::Integer,
+                    Value::String(s) => match ColumnType::infer(s) {
+                        t @ (ColumnType::Date | ColumnType::Datetime) => t,
+                        _ => ColumnType::String,
+                    },
+                    _ => ColumnType::String,
+                })
+                .fold(ColumnType::Null, ColumnType::merge)
+        })
+        .collect()
+}
+
+fn sql_type(kind: ColumnType) -> &'static str {
+    match kind {
+        ColumnType::Integer => "INTEGER",
+        ColumnType::Float => "REAL",
+        ColumnType::Boolean => "BOOLEAN",
+        ColumnType::Date => "DATE",
+        ColumnType::Datetime => "TIMESTAMP",
+        ColumnType::String | ColumnType::Null => "TEXT",
+    }
+}
+
+fn quote_ident(name: &str) -> String {
+    format!("\"{}\"", name.replace('"', "\"\""))
+}
+
+fn sql_literal(value: &Value) -> String {
+    match value {
+        Value::Null => "NULL".to_string(),
+        Value::Bool(b) => if *b { "TRUE" } else { "FALSE" }.to_string(),
+        Value::Number(n) => n.to_string(),
+        Value::String(s) => format!("'{}'", s.replace('\'', "''")),
+        v => format!("'{}'", v.to_string().replace('\'', "''")),
+    }
+}
+
+fn sql_value(value: &Value) -> SqlValue {
+    match value {
+        Value::Null => SqlValue::Null,
+        Value::Bool(b) => SqlValue::Integer(i64::from(*b)),
+        Value::Number(n) => match n.as_i64() {
+            Some(i) => SqlValue::Integer(i),
+            None => SqlValue::Real(n.as_f64().unwrap_or(f64::NAN)),
+        },
+        Value::String(s) => SqlValue::Text(s.clone()),
+        v => SqlValue::Text(v.to_string()),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use serde_json::json;
+
+    #[test]
+    fn test_render_sql() -> Result<()> {
+        let records = json!([
+            {"name": "O'Neil", "kit": 1, "born": "1990-04-18", "score": 1.5, "ok": true},
+            {"name": "Bo", "kit": 2, "born": null, "score": 2, "ok": false}
+        ]);
+        let sql = render_sql(records.as_array().unwrap(), "players")?;
+        assert_eq!(
+            sql,
+            r#"CREATE TABLE "players" (
+  "name" TEXT,
+  "kit" INTEGER,
+  "born" DATE,
+  "score" REAL,
+  "ok" BOOLEAN
+);
+BEGIN;
+INSERT INTO "players" ("name", "kit", "born", "score", "ok") VALUES ('O''Neil', 1, '1990-04-18', 1.5, TRUE);
+INSERT INTO "players" ("name", "kit", "born", "score", "ok") VALUES ('Bo', 2, NULL, 2, FALSE);
+COMMIT;
+"#
+        );
+        assert_eq!(table_name("assets/juventus.csv"), "juventus");
+        assert_eq!(table_name("2024 roster.csv"), "_2024_roster");
+        assert_eq!(table_name("-"), "data");
+        Ok(())
+    }
+
+    #[test]
+    fn test_write_sqlite() -> Result<()> {
+        let dir = tempfile::tempdir()?;
+        let path = dir.path().join("players.sqlite");
+        let path = path.to_str().unwrap();
+        let records = json!([{"name": "a", "kit": 1}, {"name": "b", "kit": 2}]);
+        write_sqlite(records.as_array().unwrap(), "players", path, false)?;
+        let err = write_sqlite(records.as_array().unwrap(), "players", path, false).unwrap_err();
+        assert!(err
+            .to_string()
+            .ends_with("already exists, use --force to replace it"));
+        write_sqlite(records.as_array().unwrap(), "players", path, true)?;
+        assert!(write_sqlite(&[], "players", path, true).is_err());
+        // sqlite reserves the name, so creating the table fails and the old database stays
+        assert!(write_sqlite(records.as_array().unwrap(), "sqlite_players", path, true).is_err());
+        assert_eq!(std::fs::read_dir(dir.path())?.count(), 1);
+
+        let conn = Connection::open(path)?;
+        let total: i64 = conn.query_row("SELECT sum(kit) FROM players", [], |r| r.get(0))?;
+        let kind: String = conn.query_row(
+            "SELECT type FROM pragma_table_info('players') WHERE name = 'kit'",
+            [],
+            |r| r.get(0),
+        )?;
+        assert_eq!(total, 3);
+        assert_eq!(kind, "INTEGER");
+        Ok(())
+    }
+}
@@ -0,0 +1,227 @@
+use std::collections::HashMap;
+
+use anyhow::Result;
+use serde::Serialize;
+
+use super::csv_convert::open_csv;
+use super::csv_dialect::CsvDialect;
+use super::csv_schema::ColumnType;
+use super::csv_show::{render_table, TableStyle};
+
+#[derive(Debug, Serialize)]
+pub struct ColumnStats {
+    pub name: String,
+    #[serde(rename = "type")]
+    pub kind: ColumnType,
+    pub rows: usize,
+    pub nulls: usize,
+    pub null_ratio: f64,
+    pub distinct: usize,
+    pub min: Option<f64>,
+    pub max: Option<f64>,
+    pub mean: Option<f64>,
+    pub longest: Option<String>,
+    pub top: Vec<ValueCount>,
+}
+
+#[derive(Debug, PartialEq, Serialize)]
+pub struct ValueCount {
+    pub value: String,
+    pub count: usize,
+}
+
+/// Running totals for a single column.
+#[derive(Default)]
+struct Profile {
+    kind: Option<ColumnType>,
+    rows: usize,
+    nulls: usize,
+    counts: HashMap<String, usize>,
+    min: Option<f64>,
+    max: Option<f64>,
+    sum: f64,
+    numbers: usize,
+    longest: Option<String>,
+}
+
+impl Profile {
+    fn add(&mut self, cell: &str) {
+        self.rows += 1;
+        let kind = ColumnType::infer(cell);
+        self.kind = Some(self.kind.unwrap_or(ColumnType::Null).merge(kind));
+        if kind == ColumnType::Null {
+            self.nulls += 1;
+            return;
+        }
+        if matches!(kind, ColumnType::Integer | ColumnType::Float) {
+            if let Ok(n) = cell.trim().parse::<f64>() {
+                self.min = Some(self.min.map_or(n, |m| m.min(n)));
+                self.max = Some(self.max.map_or(n, |m| m.max(n)));
+                self.sum += n;
+                self.numbers += 1;
+            }
+        }
+        if self
+            .longest
+            .as_ref()
+            .is_none_or(|l| cell.chars().count() > l.chars().count())
+        {
+            self.longest = Some(cell.to_string());
+        }
+        *self.counts.entry(cell.to_string()).or_default() += 1;
+    }
+
+    fn finish(self, name: &str, top: usize) -> ColumnStats {
+        let kind = match self.kind.unwrap_or(ColumnType::Null) {
+            // a column with nothing but empty cells
+            ColumnType::Null => ColumnType::String,
+            t => t,
+        };
+        let numeric = matches!(kind, ColumnType::Integer | ColumnType::Float);
+        let mut top_values = self
+            .counts
+            .iter()
+            .map(|(value, count)| ValueCount {
+                value: value.clone(),
+                count: *count,
+            })
+            .collect::<Vec<_>>();
+        top_values.sort_by(|a, b| b.count.cmp(&a.count).then_with(|| a.value.cmp(&b.value)));
+        top_values.truncate(top);
+
+        ColumnStats {
+            name: name.to_string(),
+            kind,
+            rows: self.rows,
+            nulls: self.nulls,
+            null_ratio: if self.rows == 0 {
+                0.0
+            } else {
+                self.nulls as f64 / self.rows as f64
+            },
+            distinct: self.counts.len(),
+            min: self.min.filter(|_| numeric),
+            max: self.max.filter(|_| numeric),
+            mean: (numeric && self.numbers > 0).then(|| self.sum / self.numbers as f64),
+            longest: self.longest,
+            top: top_values,
+        }
+    }
+}
+
+/// Profile every column of a csv file, keeping the `top` most frequent values of each.
+pub fn process_csv_stats(
+    input: &str,
+    dialect: &CsvDialect,
+    top: usize,
+) -> Result<Vec<ColumnStats>> {
+    let mut rdr = open_csv(input, dialect)?;
+    let headers = rdr.headers()?.clone();
+    let mut profiles = headers
+        .iter()
+        .map(|_| Profile::default())
+        .collect::<Vec<_>>();
+
+    let mut record = csv::StringRecord::new();
+    while rdr.read_record(&mut record)? {
+        for (profile, cell) in profiles.iter_mut().zip(record.iter()) {
+            profile.add(cell);
+        }
+    }
+
+    Ok(profiles
+        .into_iter()
+        .zip(headers.iter())
+        .map(|(profile, name)| profile.finish(name, top))
+        .collect())
+}
+
+/// Render column stats as a table, one row per column.
+pub fn render_stats(stats: &[ColumnStats], style: TableStyle) -> String {
+    let headers = [
+        "column", "type", "rows", "nulls", "distinct", "min", "max", "mean", "longest", "top",
+    ]
+    .map(String::from);
+    let num = |n: Option<f64>| n.map(format_number).unwrap_or_default();
+    let rows = stats
+        .iter()
+        .map(|s| {
+            let top = s
+                .top
+                .iter()
+                .map(|v| format!("{} ({})", v.value, v.count))
+                .collect::<Vec<_>>()
+                .join(", ");
+            vec![
+                s.name.clone(),
+                s.kind.to_string(),
+                s.rows.to_string(),
+                format!("{} ({:.1}%)", s.nulls, s.null_ratio * 100.0),
+                s.distinct.to_string(),
+                num(s.min),
+                num(s.max),
+                num(s.mean),
+                s.longest.clone().unwrap_or_default(),
+                top,
+            ]
+        })
+        .collect::<Vec<_>>();
+    render_table(&headers, &rows, 40, style)
+}
+
+fn format_number(n: f64) -> String {
+    if n.fract() == 0.0 {
+        format!("{}", n as i64)
+    } else {
+        format!("{:.3}", n)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_process_csv_stats() -> Result<()> {
+        let stats = process_csv_stats("assets/juventus.csv", &CsvDialect::default(), 2)?;
+        assert_eq!(stats.len(), 5);
+
+        let nationality = &stats[3];
+        assert_eq!(nationality.kind, ColumnType::String);
+        assert_eq!(nationality.rows, 27);
+        assert_eq!(
+            nationality.top,
+            [
+                ValueCount {
+                    value: "Italy".into(),
+                    count: 8
+                },
+                ValueCount {
+                    value: "Brazil".into(),
+                    count: 3
+                }
+            ]
+        );
+        assert_eq!(nationality.longest.as_deref(), Some("Bosnia-Herzegovina"));
+        assert!(nationality.mean.is_none());
+
+        let kit = &stats[4];
+        assert_eq!(kit.kind, ColumnType::Integer);
+        assert_eq!(kit.distinct, 27);
+        assert_eq!((kit.min, kit.max), (Some(1.0), Some(77.0)));
+        Ok(())
+    }
+
+    #[test]
+    fn test_profile_nulls() {
+        let mut profile = Profile::default();
+        for cell in ["1", "", "2.5", ""] {
+            profile.add(cell);
+        }
+        let stats = profile.finish("a", 5);
+        assert_eq!(stats.kind, ColumnType::Float);
+        assert_eq!(stats.nulls, 2);
+        assert_eq!(stats.null_ratio, 0.5);
+        assert_eq!(stats.mean, Some(1.75));
+    }
+}
@@ -0,0 +1,278 @@
+use std::{
+    io::{BufWriter, Read, Write},
+    time::Instant,
+};
+
+use anyhow::{anyhow, bail, Result};
+use serde_json::Value;
+
+use crate::{cli::OutputFormat, get_writer};
+
+use super::csv_convert::{
+    cell_to_string, open_csv, unflatten, CsvReadOpts, CsvWriteOpts, RecordBuilder,
+};
+use super::csv_encoding::EncodeWriter;
+use super::csv_schema::ColumnType;
+
+const PROGRESS_EVERY: u64 = 100_000;
+
+/// Convert a csv file row by row, so memory use does not grow with the input size.
+///
+/// Types can't be inferred over a whole column without reading it first, so each cell is
+/// inferred on its own unless the schema forces a type.
+pub fn process_csv_stream(
+    input: &str,
+    opts: &CsvReadOpts,
+    output: &str,
+    format: OutputFormat,
+    write_opts: &CsvWriteOpts,
+    progress: bool,
+) -> Result<()> {
+    let mut rdr = open_csv(input, &opts.dialect)?;
+    let mut wtr = BufWriter::new(EncodeWriter::new(get_writer(output)?, write_opts.encoding));
+    let count = stream_csv(&mut rdr, opts, &mut wtr, format, write_opts.nest, progress)?;
+    wtr.into_inner().map_err(|e| e.into_error())?.finish()?;
+    if progress {
+        eprintln!("converted {} rows", count);
+    }
+    Ok(())
+}
+
+fn stream_csv(
+    rdr: &mut csv::Reader<impl Read>,
+    opts: &CsvReadOpts,
+    wtr: &mut impl Write,
+    format: OutputFormat,
+    nest: bool,
+    progress: bool,
+) -> Result<u64> {
+    if matches!(
+        format,
+        OutputFormat::Toml
+            | OutputFormat::Sql
+            | OutputFormat::Sqlite
+            | OutputFormat::Xlsx
+            | OutputFormat::Markdown
+            | OutputFormat::Html
+    ) {
+        bail!(
+            "{} output can't be streamed, use json, ndjson, yaml or csv",
+            format
+        );
+    }
+
+    let headers = rdr.headers()?.clone();
+    let types = headers
+        .iter()
+        .map(|h| {
+            let forced = opts.schema.as_ref().and_then(|s| s.column(h)?.kind);
+            match forced {
+                Some(t) => Some(t),
+                None if opts.infer => None,
+                None => Some(ColumnType::String),
+            }
+        })
+        .collect();
+    let builder = RecordBuilder::new(headers, types, opts)?;
+    // rows are keyed by the renamed columns, so expressions refer to those
+    let transform = opts.transform.compile(builder.names())?;
+
+    let mut sink = match format {
+        OutputFormat::Csv => {
+            // written up front, so output without rows still has a header
+            let columns = opts.transform.output_columns(builder.names())?;
+            let mut wtr = csv::Writer::from_writer(wtr);
+            wtr.write_record(&columns)?;
+            Sink::Csv(Box::new(wtr), columns)
+        }
+        OutputFormat::Json => {
+            wtr.write_all(b"[")?;
+            Sink::Text(wtr)
+        }
+        _ => Sink::Text(wtr),
+    };
+
+    let start = Instant::now();
+    let mut record = csv::StringRecord::new();
+    let mut count = 0u64;
+    while rdr.read_record(&mut record)? {
+        let value = builder.build(&record)?;
+        let value = match value {
+            Value::Object(row) if !opts.transform.is_empty() => {
+                let line = record.position().map(|p| p.line()).unwrap_or_default();
+                match transform
+                    .apply(row)
+                    .map_err(|e| anyhow!("line {}: {}", line, e))?
+                {
+                    Some(row) => Value::Object(row),
+                    None => continue,
+                }
+            }
+            value => value,
+        };
+        match &mut sink {
+            Sink::Csv(wtr, columns) => {
+                let obj = value.as_object().expect("csv records are objects");
+                wtr.write_record(
+                    columns
+                        .iter()
+                        .map(|c| obj.get(c).map(cell_to_string).unwrap_or_default()),
+                )?;
+            }
+            Sink::Text(wtr) => {
+                let value = match value.as_object() {
+                    Some(row) if nest => unflatten(row),
+                    _ => value,
+                };
+                write_value(wtr, &value, format, count)?
+            }
+        }
+        count += 1;
+        if progress && count.is_multiple_of(PROGRESS_EVERY) {
+            eprintln!(
+                "converted {} rows ({:.0} rows/s)",
+                count,
+                count as f64 / start.elapsed().as_secs_f64()
+            );
+        }
+    }
+
+    match sink {
+        Sink::Csv(mut wtr, _) => wtr.flush()?,
+        Sink::Text(wtr) if format == OutputFormat::Json => wtr.write_all(b"\n]\n")?,
+        Sink::Text(_) => {}
+    }
+    Ok(count)
+}
+
+enum Sink<W: Write> {
+    /// csv rows are written in the order of these columns
+    Csv(Box<csv::Writer<W>>, Vec<String>),
+    Text(W),
+}
+
+fn write_value(
+    wtr: &mut impl Write,
+    value: &Value,
+    format: OutputFormat,
+    index: u64,
+) -> Result<()> {
+    match format {
+        OutputFormat::Json => {
+            if index > 0 {
+                wtr.write_all(b",")?;
+            }
+            wtr.write_all(b"\n  ")?;
+            serde_json::to_writer(wtr, value)?;
+        }
+        OutputFormat::Ndjson => {
+            serde_json::to_writer(&mut *wtr, value)?;
+            wtr.write_all(b"\n")?;
+        }
+        OutputFormat::Yaml => {
+            wtr.write_all(b"---\n")?;
+            serde_yaml::to_writer(wtr, value)?;
+        }
+        _ => unreachable!(),
+    }
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::super::csv_convert::{read_records, render_records};
+    use super::*;
+    use crate::{CsvSchema, InputFormat, RowTransform};
+
+    fn stream(data: &str, format: OutputFormat) -> Result<String> {
+        let mut rdr = csv::Reader::from_reader(data.as_bytes());
+        let opts = CsvReadOpts {
+            infer: true,
+            ..Default::default()
+        };
+        let mut buf = Vec::new();
+        stream_csv(&mut rdr, &opts, &mut buf, format, true, false)?;
+        Ok(String::from_utf8(buf)?)
+    }
+
+    #[test]
+    fn test_stream_json_array() -> Result<()> {
+        let out = stream("a,b\n1,x\n2,\n", OutputFormat::Json)?;
+        let value: Value = serde_json::from_str(&out)?;
+        assert_eq!(
+            value,
+            serde_json::json!([{"a": 1, "b": "x"}, {"a": 2, "b": null}])
+        );
+        assert_eq!(stream("a,b\n", OutputFormat::Json)?, "[\n]\n");
+        Ok(())
+    }
+
+    #[test]
+    fn test_stream_ndjson_yaml_csv() -> Result<()> {
+        let data = "a,b\n1,x\n2,y\n";
+        assert_eq!(
+            stream(data, OutputFormat::Ndjson)?,
+            "{\"a\":1,\"b\":\"x\"}\n{\"a\":2,\"b\":\"y\"}\n"
+        );
+        assert_eq!(
+            stream(data, OutputFormat::Yaml)?,
+            "---\na: 1\nb: x\n---\na: 2\nb: y\n"
+        );
+        assert_eq!(stream(data, OutputFormat::Csv)?, data);
+        assert_eq!(stream("a,b\n", OutputFormat::Csv)?, "a,b\n");
+        assert!(stream(data, OutputFormat::Toml).is_err());
+        Ok(())
+    }
+
+    #[test]
+    fn test_stream_csv_header_without_rows() -> Result<()> {
+        let mut rdr = csv::Reader::from_reader("a,b\n1,x\n2,y\n".as_bytes());
+        let opts = CsvReadOpts {
+            infer: true,
+            transform: RowTransform {
+                with: vec!["c = a * 2".to_string()],
+                filter: Some("a > 5".to_string()),
+            },
+            ..Default::default()
+        };
+        let mut buf = Vec::new();
+        let count = stream_csv(&mut rdr, &opts, &mut buf, OutputFormat::Csv, true, false)?;
+        assert_eq!(count, 0);
+        assert_eq!(String::from_utf8(buf)?, "a,b,c\n");
+        Ok(())
+    }
+
+    #[test]
+    fn test_stream_transform_renamed_columns() -> Result<()> {
+        let opts = CsvReadOpts {
+            infer: true,
+            schema: Some(CsvSchema::load("fixtures/juventus.schema.yaml")?),
+            transform: RowTransform {
+                with: vec!["Double = Kit * 2".to_string()],
+                filter: Some("Kit > 30".to_string()),
+            },
+            ..Default::default()
+        };
+        let mut rdr = open_csv("assets/juventus.csv", &opts.dialect)?;
+        let mut buf = Vec::new();
+        let count = stream_csv(&mut rdr, &opts, &mut buf, OutputFormat::Ndjson, true, false)?;
+        assert_eq!(count, 4);
+        let records = read_records("assets/juventus.csv", InputFormat::Csv, &opts)?;
+        assert_eq!(
+            String::from_utf8(buf)?,
+            render_records(&records, OutputFormat::Ndjson)?
+        );
+        Ok(())
+    }
+
+    #[test]
+    fn test_stream_nested() -> Result<()> {
+        let data = "id,address.city,tags[0],tags[1]\n1,Turin,a,b\n";
+        assert_eq!(
+            stream(data, OutputFormat::Ndjson)?,
+            "{\"id\":1,\"address\":{\"city\":\"Turin\"},\"tags\":[\"a\",\"b\"]}\n"
+        );
+        assert_eq!(stream(data, OutputFormat::Csv)?, data);
+        Ok(())
+    }
+}
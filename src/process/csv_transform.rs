@@ -0,0 +1,186 @@
+use anyhow::{anyhow, bail, Result};
+use serde_json::{Map, Value};
+
+use super::csv_expr::{parse_expr, truthy, Expr};
+
+/// Computed columns and a row filter, written as expressions.
+#[derive(Debug, Default, Clone)]
+pub struct RowTransform {
+    /// `name = expression`, evaluated in order so later ones can use earlier ones
+    pub with: Vec<String>,
+    /// rows where this isn't true are dropped, after the columns are computed
+    pub filter: Option<String>,
+}
+
+/// A [`RowTransform`] parsed against the columns of an input.
+#[derive(Debug)]
+pub struct CompiledTransform {
+    with: Vec<(String, Expr)>,
+    filter: Option<Expr>,
+}
+
+impl RowTransform {
+    pub fn is_empty(&self) -> bool {
+        self.with.is_empty() && self.filter.is_none()
+    }
+
+    /// Parse the expressions, reading bare multi-word names as the input `columns` and the
+    /// computed ones before them.
+    pub fn compile(&self, columns: &[String]) -> Result<CompiledTransform> {
+        let mut columns = columns.to_vec();
+        let mut with = Vec::new();
+        for definition in &self.with {
+            let (name, expr) = split_definition(definition)?;
+            let expr = parse_expr(expr, &columns)
+                .map_err(|e| anyhow!("invalid --with {:?}: {}", definition, e))?;
+            if !columns.contains(&name) {
+                columns.push(name.clone());
+            }
+            with.push((name, expr));
+        }
+        let filter = self
+            .filter
+            .as_deref()
+            .map(|filter| {
+                parse_expr(filter, &columns)
+                    .map_err(|e| anyhow!("invalid --where {:?}: {}", filter, e))
+            })
+            .transpose()?;
+        Ok(CompiledTransform { with, filter })
+    }
+
+    /// The columns of transformed rows: the input `columns`, then the computed ones.
+    pub fn output_columns(&self, columns: &[String]) -> Result<Vec<String>> {
+        let mut columns = columns.to_vec();
+        for definition in &self.with {
+            let (name, _) = split_definition(definition)?;
+            if !columns.contains(&name) {
+                columns.push(name);
+            }
+        }
+        Ok(columns)
+    }
+
+    /// Transform in-memory records, naming the failing row in errors.
+    pub fn apply_all(&self, records: Vec<Value>) -> Result<Vec<Value>> {
+        if self.is_empty() {
+            return Ok(records);
+        }
+        let mut columns: Vec<String> = Vec::new();
+        for name in records
+            .iter()
+            .filter_map(Value::as_object)
+            .flat_map(|r| r.keys())
+        {
+            if !columns.contains(name) {
+                columns.push(name.clone());
+            }
+        }
+        let transform = self.compile(&columns)?;
+
+        let mut rows = Vec::with_capacity(records.len());
+        for (i, record) in records.into_iter().enumerate() {
+            let Value::Object(row) = record else {
+                bail!("row {}: expected an object, got {}", i + 1, record);
+            };
+            if let Some(row) = transform
+                .apply(row)
+                .map_err(|e| anyhow!("row {}: {}", i + 1, e))?
+            {
+                rows.push(Value::Object(row));
+            }
+        }
+        Ok(rows)
+    }
+}
+
+impl CompiledTransform {
+    /// Add the computed columns to `row`, or drop it if it doesn't match the filter.
+    pub fn apply(&self, mut row: Map<String, Value>) -> Result<Option<Map<String, Value>>> {
+        for (name, expr) in &self.with {
+            let value = expr.eval(&row)?;
+            row.insert(name.clone(), value);
+        }
+        match &self.filter {
+            Some(filter) if !truthy(&filter.eval(&row)?) => Ok(None),
+            _ => Ok(Some(row)),
+        }
+    }
+}
+
+/// Split `name = expression` at its first `=` that isn't part of a comparison.
+fn split_definition(definition: &str) -> Result<(String, &str)> {
+    let bytes = definition.as_bytes();
+    let at = (0..bytes.len())
+        .find(|&i| {
+            bytes[i] == b'='
+                && !matches!(bytes.get(i + 1), Some(b'='))
+                && !matches!(
+                    i.checked_sub(1).map(|j| bytes[j]),
+                    Some(b'<' | b'>' | b'!' | b'=')
+                )
+        })
+        .ok_or_else(|| anyhow!("expected name = expression, got {:?}", definition))?;
+    let name = definition[..at].trim();
+    let name = name
+        .strip_prefix(['"', '`'])
+        .and_then(|n| n.strip_suffix(['"', '`']))
+        .unwrap_or(name);
+    if name.is_empty() {
+        bail!("missing column name in {:?}", definition);
+    }
+    Ok((name.to_string(), &definition[at + 1..]))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use serde_json::json;
+
+    #[test]
+    fn test_apply_transform() -> Result<()> {
+        let records = json!([
+            {"Name": " Wojciech Szczesny ", "DOB": "Apr 18, 1990 (29)", "Kit Number": 1},
+            {"Name": "Paulo Dybala", "DOB": "Nov 15, 1993 (25)", "Kit Number": 10},
+            {"Name": "Gianluigi Buffon", "DOB": "Jan 28, 1978 (41)", "Kit Number": 77},
+        ]);
+        let transform = RowTransform {
+            with: vec![
+                "age = 2024 - year(DOB)".to_string(),
+                "Name = upper(trim(Name))".to_string(),
+                "\"Kit Band\" = Kit Number >= 10".to_string(),
+            ],
+            filter: Some("Kit Number > 5 and age < 40".to_string()),
+        };
+        let rows = transform.apply_all(records.as_array().unwrap().clone())?;
+        assert_eq!(
+            rows,
+            [json!({
+                "Name": "PAULO DYBALA",
+                "DOB": "Nov 15, 1993 (25)",
+                "Kit Number": 10,
+                "age": 31,
+                "Kit Band": true
+            })]
+        );
+        Ok(())
+    }
+
+    #[test]
+    fn test_transform_errors() {
+        let records = vec![json!({"a": 1}), json!({"a": "x"})];
+        let transform = RowTransform {
+            with: vec!["b = a * 2".to_string()],
+            filter: None,
+        };
+        let err = transform.apply_all(records).unwrap_err();
+        assert_eq!(err.to_string(), "row 2: can't apply Mul to \"x\" and 2");
+
+        assert!(split_definition("a == 1").is_err());
+        assert!(split_definition(" = 1").is_err());
+        assert_eq!(
+            split_definition("ok = a <= 1").unwrap(),
+            ("ok".to_string(), " a <= 1")
+        );
+    }
+}
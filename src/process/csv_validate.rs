@@ -0,0 +1,237 @@
+use std::{collections::HashMap, fmt};
+
+use anyhow::Result;
+use regex::Regex;
+use serde::Serialize;
+
+use super::csv_convert::open_csv;
+use super::csv_dialect::CsvDialect;
+use super::csv_expr::to_number;
+use super::csv_schema::{ColumnSchema, ColumnType, CsvSchema};
+
+#[derive(Debug, PartialEq, Serialize)]
+pub struct Violation {
+    /// line number in the file, `None` for problems with the header itself
+    pub row: Option<u64>,
+    pub column: String,
+    pub reason: String,
+}
+
+/// A schema column bound to its position in the file, with its pattern compiled.
+struct Rule<'a> {
+    index: usize,
+    schema: &'a ColumnSchema,
+    pattern: Option<Regex>,
+    seen: HashMap<String, u64>,
+}
+
+/// Check every row of a csv file against a schema and collect all violations.
+pub fn process_csv_validate(
+    input: &str,
+    dialect: &CsvDialect,
+    schema: &CsvSchema,
+) -> Result<Vec<Violation>> {
+    let mut rdr = open_csv(input, dialect)?;
+    let headers = rdr.headers()?.clone();
+    let mut violations = Vec::new();
+    let header_violation = |column: &str, reason: &str| Violation {
+        row: None,
+        column: column.to_string(),
+        reason: reason.to_string(),
+    };
+
+    let mut rules = Vec::new();
+    for column in &schema.columns {
+        match headers.iter().position(|h| h == column.name) {
+            Some(index) => rules.push(Rule {
+                index,
+                schema: column,
+                pattern: column.pattern.as_deref().map(Regex::new).transpose()?,
+                seen: HashMap::new(),
+            }),
+            None if column.required => {
+                violations.push(header_violation(&column.name, "required column is missing"))
+            }
+            None => {}
+        }
+    }
+    if schema.strict {
+        for header in headers.iter() {
+            if schema.column(header).is_none() {
+                violations.push(header_violation(
+                    header,
+                    "column is not declared in the schema",
+                ));
+            }
+        }
+    }
+
+    let mut record = csv::StringRecord::new();
+    while rdr.read_record(&mut record)? {
+        let line = record.position().map(|p| p.line()).unwrap_or_default();
+        for rule in rules.iter_mut() {
+            let cell = record.get(rule.index).unwrap_or_default();
+            for reason in rule.check(cell, line) {
+                violations.push(Violation {
+                    row: Some(line),
+                    column: rule.schema.name.clone(),
+                    reason,
+                });
+            }
+        }
+    }
+    Ok(violations)
+}
+
+impl Rule<'_> {
+    /// Every rule a cell breaks. Empty cells are only checked for being allowed to be empty.
+    fn check(&mut self, cell: &str, line: u64) -> Vec<String> {
+        let schema = self.schema;
+        let mut reasons = Vec::new();
+        if cell.trim().is_empty() {
+            if !schema.nullable {
+                reasons.push("value is empty".to_string());
+            }
+            return reasons;
+        }
+        if let Some(kind) = schema.kind {
+            if kind != ColumnType::String && kind.parse(cell).is_err() {
+                reasons.push(format!("{:?} is not a valid {}", cell, kind));
+            }
+        }
+        if let Some(pattern) = &self.pattern {
+            if !pattern.is_match(cell) {
+                reasons.push(format!("{:?} doesn't match pattern {}", cell, pattern));
+            }
+        }
+        if let Some(allowed) = &schema.allowed {
+            if !allowed.iter().any(|a| a == cell) {
+                reasons.push(format!("{:?} is not one of {}", cell, allowed.join(", ")));
+            }
+        }
+        if schema.min.is_some() || schema.max.is_some() {
+            match to_number(&cell.into()) {
+                None => reasons.push(format!("{:?} is not a number", cell)),
+                Some(n) => {
+                    if let Some(min) = schema.min.filter(|min| n < *min) {
+                        reasons.push(format!("{} is less than {}", cell, min));
+                    }
+                    if let Some(max) = schema.max.filter(|max| n > *max) {
+                        reasons.push(format!("{} is greater than {}", cell, max));
+                    }
+                }
+            }
+        }
+        let len = cell.chars().count();
+        if let Some(min) = schema.min_length.filter(|min| len < *min) {
+            reasons.push(format!("{:?} is shorter than {} characters", cell, min));
+        }
+        if let Some(max) = schema.max_length.filter(|max| len > *max) {
+            reasons.push(format!("{:?} is longer than {} characters", cell, max));
+        }
+        // a value breaking other rules still counts, so its duplicates are reported too
+        if schema.unique {
+            match self.seen.get(cell) {
+                Some(first) => reasons.push(format!(
+                    "duplicate value {:?}, first seen at row {}",
+                    cell, first
+                )),
+                None => {
+                    self.seen.insert(cell.to_string(), line);
+                }
+            }
+        }
+        reasons
+    }
+}
+
+impl fmt::Display for Violation {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self.row {
+            Some(row) => write!(f, "row {}, column {:?}: {}", row, self.column, self.reason),
+            None => write!(f, "header, column {:?}: {}", self.column, self.reason),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_validate_juventus() -> Result<()> {
+        let schema = CsvSchema::load("fixtures/juventus.schema.yaml")?;
+        let violations =
+            process_csv_validate("assets/juventus.csv", &CsvDialect::default(), &schema)?;
+        assert_eq!(violations, []);
+        Ok(())
+    }
+
+    #[test]
+    fn test_validate_violations() -> Result<()> {
+        let schema: CsvSchema = serde_yaml::from_str(
+            r#"
+strict: true
+columns:
+  - name: Position
+    enum: [Goalkeeper, Centre-Back]
+  - name: Nationality
+    unique: true
+  - name: Kit Number
+    max: 30
+  - name: Age
+    required: true
+"#,
+        )?;
+        let violations =
+            process_csv_validate("assets/juventus.csv", &CsvDialect::default(), &schema)?;
+        assert_eq!(
+            violations[0].to_string(),
+            "header, column \"Age\": required column is missing"
+        );
+        assert_eq!(violations[1].column, "Name");
+        assert_eq!(violations[2].column, "DOB");
+        assert_eq!(
+            violations[3].to_string(),
+            "row 3, column \"Kit Number\": 37 is greater than 30"
+        );
+        assert_eq!(
+            violations[4].to_string(),
+            "row 4, column \"Nationality\": duplicate value \"Italy\", first seen at row 3"
+        );
+        assert!(violations
+            .iter()
+            .any(|v| v.reason == "\"Left-Back\" is not one of Goalkeeper, Centre-Back"));
+        Ok(())
+    }
+
+    #[test]
+    fn test_validate_all_rules_of_a_cell() -> Result<()> {
+        let schema: CsvSchema = serde_yaml::from_str(
+            r#"
+columns:
+  - name: Kit Number
+    unique: true
+    max: 5
+    pattern: "^[0-9]$"
+"#,
+        )?;
+        let dir = tempfile::tempdir()?;
+        let path = dir.path().join("kits.csv");
+        std::fs::write(&path, "Kit Number\n10\n10\n")?;
+        let violations =
+            process_csv_validate(path.to_str().unwrap(), &CsvDialect::default(), &schema)?;
+        let reasons = violations.iter().map(|v| v.to_string()).collect::<Vec<_>>();
+        assert_eq!(
+            reasons,
+            [
+                "row 2, column \"Kit Number\": \"10\" doesn't match pattern ^[0-9]$",
+                "row 2, column \"Kit Number\": 10 is greater than 5",
+                "row 3, column \"Kit Number\": \"10\" doesn't match pattern ^[0-9]$",
+                "row 3, column \"Kit Number\": 10 is greater than 5",
+                "row 3, column \"Kit Number\": duplicate value \"10\", first seen at row 2",
+            ]
+        );
+        Ok(())
+    }
+}
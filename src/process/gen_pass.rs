@@ -1,53 +1,334 @@
-use anyhow::Result;
-use rand::seq::SliceRandom;
+use std::{collections::BTreeMap, fs, path::Path};
+
+use anyhow::{anyhow, bail, Result};
+use rand::{seq::SliceRandom, Rng};
+use serde::Deserialize;
 
 const UPPER: &[u8] = b"ABCDEFGHJKLMNPQRSTUVWXYZ";
 const LOWER: &[u8] = b"abcdefghijkmnopqrstuvwxyz";
 const NUMBER: &[u8] = b"23456789";
-const SYMBOL: &[u8] = b"!@#$%^&*_";
-pub fn process_genpass(
-    length: u8,
-    upper: bool,
-    lower: bool,
-    number: bool,
-    symbol: bool,
-) -> Result<String> {
-    let rng = &mut rand::thread_rng();
-    let mut chars = Vec::new();
-    let mut password = Vec::with_capacity(length as usize);
-
-    if upper {
-        chars.extend_from_slice(UPPER);
-        // password.push(UPPER.choose(rng).map(|c| *c).unwrap());
-        password.push(UPPER.choose(rng).copied().unwrap());
-    }
-    if lower {
-        chars.extend_from_slice(LOWER);
-        password.push(LOWER.choose(rng).copied().unwrap());
-    }
-    if number {
-        chars.extend_from_slice(NUMBER);
-        password.push(NUMBER.choose(rng).copied().unwrap());
-    }
-    if symbol {
-        chars.extend_from_slice(SYMBOL);
-        password.push(SYMBOL.choose(rng).copied().unwrap());
-    }
-
-    let mut remain = length as isize - password.len() as isize;
-    if remain < 0 {
-        remain = 0;
-    }
-    password.extend_from_slice(
-        chars
-            .choose_multiple(rng, remain as usize)
-            .copied()
-            .collect::<Vec<u8>>()
-            .as_slice(),
-    );
-
-    password.shuffle(rng);
-    let password = String::from_utf8(password)?;
-
-    Ok(password)
+pub(super) const SYMBOL: &[u8] = b"!@#$%^&*_";
+
+/// What a password is made of: an alphabet and a minimum count per character class. A class
+/// with an empty alphabet is left out, whatever its minimum.
+#[derive(Debug, Clone, PartialEq, Eq, Deserialize)]
+#[serde(default, deny_unknown_fields)]
+pub struct PasswordPolicy {
+    pub length: usize,
+    pub upper: String,
+    pub lower: String,
+    pub digits: String,
+    pub symbols: String,
+    pub min_upper: usize,
+    pub min_lower: usize,
+    pub min_digits: usize,
+    pub min_symbols: usize,
+    /// characters never used, eg: look-alikes like lI1O0
+    pub exclude: String,
+}
+
+impl Default for PasswordPolicy {
+    fn default() -> Self {
+        Self {
+            length: 16,
+            upper: String::from_utf8_lossy(UPPER).into_owned(),
+            lower: String::from_utf8_lossy(LOWER).into_owned(),
+            digits: String::from_utf8_lossy(NUMBER).into_owned(),
+            symbols: String::from_utf8_lossy(SYMBOL).into_owned(),
+            min_upper: 1,
+            min_lower: 1,
+            min_digits: 1,
+            min_symbols: 1,
+            exclude: String::new(),
+        }
+    }
+}
+
+/// A character class with the excluded characters taken out.
+struct CharClass {
+    name: &'static str,
+    chars: Vec<char>,
+    min: usize,
+}
+
+impl PasswordPolicy {
+    /// Load the policy called `name` from a yaml file mapping names to policies, so one file
+    /// can describe what every target system accepts.
+    pub fn load(path: impl AsRef<Path>, name: &str) -> Result<Self> {
+        let path = path.as_ref();
+        let content = fs::read_to_string(path)?;
+        let mut policies: BTreeMap<String, PasswordPolicy> = serde_yaml::from_str(&content)?;
+        let names = policies.keys().cloned().collect::<Vec<_>>();
+        policies.remove(name).ok_or_else(|| {
+            anyhow!(
+                "no policy {:?} in {}, it has: {}",
+                name,
+                path.display(),
+                names.join(", ")
+            )
+        })
+    }
+
+    fn classes(&self) -> Result<Vec<CharClass>> {
+        let classes = [
+            ("upper", &self.upper, self.min_upper),
+            ("lower", &self.lower, self.min_lower),
+            ("digits", &self.digits, self.min_digits),
+            ("symbols", &self.symbols, self.min_symbols),
+        ];
+        let mut res = Vec::new();
+        for (name, chars, min) in classes {
+            if chars.is_empty() {
+                continue;
+            }
+            let mut allowed = Vec::new();
+            for c in chars.chars() {
+                if !self.exclude.contains(c) && !allowed.contains(&c) {
+                    allowed.push(c);
+                }
+            }
+            if allowed.is_empty() && min > 0 {
+                bail!(
+                    "at least {} {} required, but all of them are excluded",
+                    min,
+                    name
+                );
+            }
+            res.push(CharClass {
+                name,
+                chars: allowed,
+                min,
+            });
+        }
+        Ok(res)
+    }
+
+    /// What `password` breaks in this policy. Letters and digits are checked by category, so
+    /// look-alikes missing from the default alphabets still count, symbols have to be allowed.
+    pub fn violations(&self, password: &str) -> Vec<String> {
+        let mut res = Vec::new();
+        if password.chars().count() < self.length {
+            res.push(format!("shorter than {} characters", self.length));
+        }
+        let classes = [
+            ("upper", &self.upper, self.min_upper),
+            ("lower", &self.lower, self.min_lower),
+            ("digits", &self.digits, self.min_digits),
+            ("symbols", &self.symbols, self.min_symbols),
+        ];
+        for (name, chars, min) in classes {
+            let count = password.chars().filter(|c| char_class(*c) == name).count();
+            if chars.is_empty() {
+                if count > 0 {
+                    res.push(format!("{} aren't allowed", name));
+                }
+            } else if count < min {
+                res.push(format!("fewer than {} {}", min, name));
+            }
+        }
+        if !self.symbols.is_empty()
+            && password
+                .chars()
+                .any(|c| char_class(c) == "symbols" && !self.symbols.contains(c))
+        {
+            res.push(format!("symbols other than {}", self.symbols));
+        }
+        if password.chars().any(|c| self.exclude.contains(c)) {
+            res.push("excluded characters".to_string());
+        }
+        res
+    }
+
+    /// Generate a password meeting the minimum of every class, the rest drawn from all the
+    /// allowed characters.
+    pub fn generate(&self, rng: &mut impl Rng) -> Result<String> {
+        if self.length == 0 {
+            bail!("a password needs at least one character");
+        }
+        let classes = self.classes()?;
+        let required = classes.iter().map(|c| c.min).sum::<usize>();
+        if required > self.length {
+            let mins = classes
+                .iter()
+                .filter(|c| c.min > 0)
+                .map(|c| format!("{} {}", c.min, c.name))
+                .collect::<Vec<_>>();
+            bail!(
+                "a password of {} characters can't hold {}",
+                self.length,
+                mins.join(" + ")
+            );
+        }
+        let mut all = Vec::new();
+        for c in classes.iter().flat_map(|class| &class.chars) {
+            if !all.contains(c) {
+                all.push(*c);
+            }
+        }
+        if all.is_empty() {
+            bail!("the policy allows no characters at all");
+        }
+
+        let mut password: Vec<char> = Vec::with_capacity(self.length);
+        for class in &classes {
+            for _ in 0..class.min {
+                password.extend(class.chars.choose(rng));
+            }
+        }
+        while password.len() < self.length {
+            password.extend(all.choose(rng));
+        }
+        password.shuffle(rng);
+        Ok(password.into_iter().collect())
+    }
+}
+
+/// The class a character of a checked password counts towards.
+fn char_class(c: char) -> &'static str {
+    if c.is_uppercase() {
+        "upper"
+    } else if c.is_lowercase() {
+        "lower"
+    } else if c.is_ascii_digit() {
+        "digits"
+    } else if c.is_alphanumeric() {
+        "other"
+    } else {
+        "symbols"
+    }
+}
+
+pub fn process_genpass(policy: &PasswordPolicy, rng: &mut impl Rng) -> Result<String> {
+    policy.generate(rng)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use rand::SeedableRng;
+    use rand_chacha::ChaCha20Rng;
+
+    fn count(password: &str, chars: &str) -> usize {
+        password.chars().filter(|c| chars.contains(*c)).count()
+    }
+
+    #[test]
+    fn test_generate_with_policy() -> Result<()> {
+        let policy = PasswordPolicy {
+            length: 12,
+            symbols: "-_.".to_string(),
+            min_upper: 2,
+            min_symbols: 3,
+            exclude: "ABCDEFGH".to_string(),
+            ..Default::default()
+        };
+        for _ in 0..20 {
+            let password = process_genpass(&policy, &mut rand::thread_rng())?;
+            assert_eq!(password.chars().count(), 12);
+            assert!(count(&password, "-_.") >= 3);
+            assert!(password.chars().filter(char::is_ascii_uppercase).count() >= 2);
+            assert_eq!(count(&password, "ABCDEFGH!@#$%^&*"), 0);
+        }
+
+        // longer than the alphabet, and without symbols
+        let policy = PasswordPolicy {
+            length: 100,
+            symbols: String::new(),
+            ..Default::default()
+        };
+        let password = process_genpass(&policy, &mut rand::thread_rng())?;
+        assert_eq!(password.len(), 100);
+        assert!(password.chars().all(char::is_alphanumeric));
+        Ok(())
+    }
+
+    #[test]
+    fn test_invalid_policy() {
+        let policy = PasswordPolicy {
+            length: 4,
+            min_symbols: 3,
+            ..Default::default()
+        };
+        assert_eq!(
+            process_genpass(&policy, &mut rand::thread_rng())
+                .unwrap_err()
+                .to_string(),
+            "a password of 4 characters can't hold 1 upper + 1 lower + 1 digits + 3 symbols"
+        );
+        let policy = PasswordPolicy {
+            digits: "123".to_string(),
+            exclude: "0123".to_string(),
+            ..Default::default()
+        };
+        assert!(process_genpass(&policy, &mut rand::thread_rng()).is_err());
+        let policy = PasswordPolicy {
+            length: 0,
+            min_upper: 0,
+            min_lower: 0,
+            min_digits: 0,
+            min_symbols: 0,
+            ..Default::default()
+        };
+        assert_eq!(
+            process_genpass(&policy, &mut rand::thread_rng())
+                .unwrap_err()
+                .to_string(),
+            "a password needs at least one character"
+        );
+    }
+
+    #[test]
+    fn test_policy_violations() {
+        let policy = PasswordPolicy {
+            length: 12,
+            symbols: "-_.".to_string(),
+            min_symbols: 2,
+            exclude: "O0".to_string(),
+            ..Default::default()
+        };
+        assert!(policy.violations("Il1-correct_horse").is_empty());
+        assert_eq!(
+            policy.violations("P@ss0rd"),
+            [
+                "shorter than 12 characters",
+                "fewer than 2 symbols",
+                "symbols other than -_.",
+                "excluded characters"
+            ]
+        );
+        let policy = PasswordPolicy {
+            symbols: String::new(),
+            ..Default::default()
+        };
+        assert_eq!(
+            policy.violations("Juventus-Turin-1897"),
+            ["symbols aren't allowed"]
+        );
+    }
+
+    #[test]
+    fn test_seeded_passwords() -> Result<()> {
+        let policy = PasswordPolicy::default();
+        let first = process_genpass(&policy, &mut ChaCha20Rng::seed_from_u64(1897))?;
+        let again = process_genpass(&policy, &mut ChaCha20Rng::seed_from_u64(1897))?;
+        let other = process_genpass(&policy, &mut ChaCha20Rng::seed_from_u64(1898))?;
+        assert_eq!(first, again);
+        assert_ne!(first, other);
+        Ok(())
+    }
+
+    #[test]
+    fn test_load_policy() -> Result<()> {
+        let policy = PasswordPolicy::load("fixtures/genpass-policies.yaml", "legacy-erp")?;
+        assert_eq!(policy.length, 12);
+        assert!(policy.symbols.is_empty());
+        assert_eq!(policy.upper, PasswordPolicy::default().upper);
+
+        let err = PasswordPolicy::load("fixtures/genpass-policies.yaml", "mainframe").unwrap_err();
+        assert_eq!(
+            err.to_string(),
+            "no policy \"mainframe\" in fixtures/genpass-policies.yaml, it has: cloud, legacy-erp"
+        );
+        Ok(())
+    }
 }
@@ -0,0 +1,173 @@
+use std::{collections::HashSet, fs, path::Path};
+
+use anyhow::{bail, Result};
+use rand::{seq::SliceRandom, Rng};
+
+use super::gen_pass::SYMBOL;
+
+const EFF_LARGE_WORDLIST: &str = include_str!("../../assets/eff_large_wordlist.txt");
+const DIGITS: &[u8] = b"0123456789";
+/// Fewer distinct words than this would make passphrases trivially guessable.
+const MIN_WORDLIST_LEN: usize = 64;
+
+/// Distinct words to draw passphrases from.
+#[derive(Debug, Clone)]
+pub struct Wordlist {
+    words: Vec<String>,
+}
+
+/// How a passphrase is put together.
+#[derive(Debug, Clone, Copy)]
+pub struct PassphraseSpec<'a> {
+    pub words: usize,
+    pub separator: &'a str,
+    /// start every word with a capital letter
+    pub capitalize: bool,
+    /// append a random digit to a random word
+    pub digit: bool,
+    /// append a random symbol to a random word
+    pub symbol: bool,
+}
+
+#[derive(Debug, Clone)]
+pub struct Passphrase {
+    pub phrase: String,
+    /// bits of entropy for an attacker who knows the wordlist and the spec
+    pub entropy: f64,
+}
+
+impl AsRef<str> for Passphrase {
+    fn as_ref(&self) -> &str {
+        &self.phrase
+    }
+}
+
+impl Wordlist {
+    /// The EFF large wordlist, 7776 words picked to be easy to type and remember.
+    pub fn eff() -> Self {
+        Self::parse(EFF_LARGE_WORDLIST)
+    }
+
+    /// Load a wordlist with one word per line. Anything before the word on a line, like the
+    /// dice rolls of diceware lists, is ignored, and so are repeated words.
+    pub fn load(path: impl AsRef<Path>) -> Result<Self> {
+        let list = Self::parse(&fs::read_to_string(path)?);
+        if list.words.len() < MIN_WORDLIST_LEN {
+            bail!(
+                "the wordlist has {} distinct words, it needs at least {}",
+                list.words.len(),
+                MIN_WORDLIST_LEN
+            );
+        }
+        Ok(list)
+    }
+
+    fn parse(content: &str) -> Self {
+        let mut seen = HashSet::new();
+        let words = content
+            .lines()
+            .filter_map(|line| line.split_whitespace().last())
+            .filter(|word| seen.insert(*word))
+            .map(String::from)
+            .collect();
+        Self { words }
+    }
+
+    pub fn len(&self) -> usize {
+        self.words.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.words.is_empty()
+    }
+}
+
+pub fn process_genphrase(
+    wordlist: &Wordlist,
+    spec: &PassphraseSpec,
+    rng: &mut impl Rng,
+) -> Result<Passphrase> {
+    if spec.words == 0 {
+        bail!("a passphrase needs at least one word");
+    }
+    if wordlist.is_empty() {
+        bail!("the wordlist is empty");
+    }
+    let mut words = (0..spec.words)
+        .map(|_| wordlist.words.choose(rng).cloned().unwrap_or_default())
+        .collect::<Vec<_>>();
+    let mut entropy = spec.words as f64 * (wordlist.len() as f64).log2();
+
+    if spec.capitalize {
+        for word in words.iter_mut() {
+            *word = capitalize(word);
+        }
+    }
+    // the capitalization is fixed, but where the extra characters go adds to the entropy
+    for (enabled, chars) in [(spec.digit, DIGITS), (spec.symbol, SYMBOL)] {
+        if enabled {
+            let c = chars.choose(rng).copied().unwrap_or_default();
+            words[rng.gen_range(0..spec.words)].push(c as char);
+            entropy += (chars.len() as f64 * spec.words as f64).log2();
+        }
+    }
+
+    Ok(Passphrase {
+        phrase: words.join(spec.separator),
+        entropy,
+    })
+}
+
+fn capitalize(word: &str) -> String {
+    let mut chars = word.chars();
+    match chars.next() {
+        Some(first) => first.to_uppercase().chain(chars).collect(),
+        None => String::new(),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_eff_wordlist() {
+        let list = Wordlist::eff();
+        assert_eq!(list.len(), 7776);
+        assert_eq!(list.words[0], "abacus");
+        assert_eq!(list.words[7775], "zoom");
+    }
+
+    #[test]
+    fn test_generate_passphrase() -> Result<()> {
+        let spec = PassphraseSpec {
+            words: 6,
+            separator: "-",
+            capitalize: true,
+            digit: true,
+            symbol: false,
+        };
+        let passphrase = process_genphrase(&Wordlist::eff(), &spec, &mut rand::thread_rng())?;
+        let words = passphrase.phrase.split('-').collect::<Vec<_>>();
+        assert_eq!(words.len(), 6);
+        assert!(words.iter().all(|w| w.starts_with(char::is_uppercase)));
+        assert_eq!(
+            passphrase
+                .phrase
+                .chars()
+                .filter(char::is_ascii_digit)
+                .count(),
+            1
+        );
+        // 6 words of 12.9 bits, plus a digit in one of 6 places
+        assert!((passphrase.entropy - (6.0 * 7776f64.log2() + 60f64.log2())).abs() < 1e-9);
+        Ok(())
+    }
+
+    #[test]
+    fn test_load_wordlist() {
+        let list = Wordlist::parse("11111\tabacus\n11112 abdomen\nabacus\n\nzoom\n");
+        assert_eq!(list.words, ["abacus", "abdomen", "zoom"]);
+        assert!(Wordlist::load("fixtures/pass.txt").is_err());
+    }
+}
@@ -1,14 +1,59 @@
 mod b64;
+mod csv_cat;
 mod csv_convert;
+mod csv_dialect;
+mod csv_diff;
+mod csv_encoding;
+mod csv_expr;
+mod csv_join;
+mod csv_markup;
+mod csv_protect;
+mod csv_query;
+mod csv_reshape;
+mod csv_schema;
+mod csv_sheet;
+mod csv_show;
+mod csv_sort;
+mod csv_split;
+mod csv_sql;
+mod csv_stats;
+mod csv_stream;
+mod csv_transform;
+mod csv_validate;
 mod gen_pass;
+mod gen_phrase;
 mod http_serve;
 mod jwt;
+mod pass_check;
+mod pass_strength;
 mod text;
 
 pub use b64::{process_decode, process_encode};
-pub use csv_convert::process_csv;
-pub use gen_pass::process_genpass;
+pub use csv_cat::process_csv_cat;
+pub use csv_convert::{process_csv, render_records, write_records, CsvReadOpts, CsvWriteOpts};
+pub use csv_dialect::CsvDialect;
+pub use csv_diff::{process_csv_diff, render_diff, render_patch, CsvDiff};
+pub use csv_encoding::TextEncoding;
+pub use csv_join::process_csv_join;
+pub use csv_protect::{process_csv_protect, process_csv_unprotect};
+pub use csv_query::process_csv_query;
+pub use csv_reshape::{process_csv_melt, process_csv_pivot, PivotSpec};
+pub use csv_schema::{ColumnType, CsvSchema};
+pub use csv_show::{process_csv_show, render_table, TableStyle};
+pub use csv_sort::{process_csv_sort, SortSpec};
+pub use csv_split::{process_csv_split, SplitBy, SplitChunk};
+pub use csv_sql::{render_sql, table_name, write_sqlite};
+pub use csv_stats::{process_csv_stats, render_stats, ColumnStats};
+pub use csv_stream::process_csv_stream;
+pub use csv_transform::RowTransform;
+pub use csv_validate::{process_csv_validate, Violation};
+pub use gen_pass::{process_genpass, PasswordPolicy};
+pub use gen_phrase::{process_genphrase, Passphrase, PassphraseSpec, Wordlist};
 pub use http_serve::process_http_serve;
+pub use pass_check::{process_genpass_check, PasswordCheck};
+pub use pass_strength::{
+    generate_scored, process_strength, render_strength, CrackTime, CrackTimes, StrengthReport,
+};
 pub use text::{
     process_text_decrypt, process_text_encrypt, process_text_generate, process_text_sign,
     process_text_verify,
@@ -0,0 +1,101 @@
+use std::{
+    collections::HashMap,
+    io::{BufRead, BufReader},
+};
+
+use anyhow::Result;
+use serde::Serialize;
+
+use crate::get_reader;
+
+use super::gen_pass::PasswordPolicy;
+use super::pass_strength::process_strength;
+
+/// The audit of one password. The password itself is left out, rows point at its line.
+#[derive(Debug, Clone, Serialize)]
+pub struct PasswordCheck {
+    pub line: usize,
+    pub length: usize,
+    pub score: u8,
+    pub guesses_log10: f64,
+    /// line of the first occurrence of the same password
+    pub duplicate_of: Option<usize>,
+    /// policy rules the password breaks, separated by `; `
+    pub violations: String,
+    pub warning: Option<String>,
+}
+
+/// Check every password of `input`, one per line, blank lines skipped. `user_inputs` like
+/// usernames are words zxcvbn assumes an attacker tries first.
+pub fn process_genpass_check(
+    input: &str,
+    user_inputs: &[&str],
+    policy: &PasswordPolicy,
+) -> Result<Vec<PasswordCheck>> {
+    let reader = BufReader::new(get_reader(input)?);
+    let mut seen: HashMap<String, usize> = HashMap::new();
+    let mut checks = Vec::new();
+    for (i, line) in reader.lines().enumerate() {
+        let line = line?;
+        let password = line.strip_suffix('\r').unwrap_or(&line);
+        if password.is_empty() {
+            continue;
+        }
+        let report = process_strength(password, user_inputs)?;
+        checks.push(PasswordCheck {
+            line: i + 1,
+            length: password.chars().count(),
+            score: report.score,
+            guesses_log10: report.guesses_log10,
+            duplicate_of: seen.get(password).copied(),
+            violations: policy.violations(password).join("; "),
+            warning: report.warning,
+        });
+        seen.entry(password.to_string()).or_insert(i + 1);
+    }
+    Ok(checks)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::fs;
+
+    #[test]
+    fn test_check_passwords() -> Result<()> {
+        let dir = tempfile::tempdir()?;
+        let path = dir.path().join("passwords.txt");
+        let strong = fs::read_to_string("fixtures/pass.txt")?;
+        fs::write(
+            &path,
+            format!(
+                "{}\r\ndybala10\n\nJuventus-Turin\n{}",
+                strong.trim(),
+                strong
+            ),
+        )?;
+        let checks = process_genpass_check(
+            path.to_str().unwrap(),
+            &["dybala"],
+            &PasswordPolicy::default(),
+        )?;
+        assert_eq!(
+            checks.iter().map(|c| c.line).collect::<Vec<_>>(),
+            [1, 2, 4, 5]
+        );
+        assert_eq!(checks[0].score, 4);
+        assert_eq!(checks[0].violations, "");
+        assert_eq!(checks[0].duplicate_of, None);
+        assert_eq!(checks[3].duplicate_of, Some(1));
+        assert!(checks[1].score <= 1);
+        assert_eq!(
+            checks[1].violations,
+            "shorter than 16 characters; fewer than 1 upper; fewer than 1 symbols"
+        );
+        assert_eq!(
+            checks[2].violations,
+            "shorter than 16 characters; fewer than 1 digits; symbols other than !@#$%^&*_"
+        );
+        Ok(())
+    }
+}
@@ -0,0 +1,163 @@
+use std::fmt::Write;
+
+use anyhow::{bail, Result};
+use serde::Serialize;
+use zxcvbn::{time_estimates::CrackTimeSeconds, zxcvbn, Entropy};
+
+/// Give up on a minimum score after this many candidates, the policy can't reach it.
+const MAX_ATTEMPTS: usize = 100;
+
+/// How hard a password is to guess, as estimated by zxcvbn.
+#[derive(Debug, Clone, Serialize)]
+pub struct StrengthReport {
+    /// 0 (too guessable) to 4 (very unguessable)
+    pub score: u8,
+    pub guesses_log10: f64,
+    /// bits of entropy of the generator, when it's known
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub entropy: Option<f64>,
+    pub crack_times: CrackTimes,
+    pub warning: Option<String>,
+    pub suggestions: Vec<String>,
+}
+
+/// Time to guess the password in the attack scenarios zxcvbn models.
+#[derive(Debug, Clone, Serialize)]
+pub struct CrackTimes {
+    /// an online attack on a service limiting attempts to 100 per hour
+    pub online_throttled: CrackTime,
+    /// an online attack on a service without rate limits, 10 attempts per second
+    pub online_unthrottled: CrackTime,
+    /// an offline attack on a slow hash like bcrypt, 1e4 attempts per second
+    pub offline_slow_hashing: CrackTime,
+    /// an offline attack on a fast hash like md5, 1e10 attempts per second
+    pub offline_fast_hashing: CrackTime,
+}
+
+#[derive(Debug, Clone, Serialize)]
+pub struct CrackTime {
+    pub seconds: f64,
+    pub display: String,
+}
+
+impl From<CrackTimeSeconds> for CrackTime {
+    fn from(time: CrackTimeSeconds) -> Self {
+        let seconds = match time {
+            CrackTimeSeconds::Integer(i) => i as f64,
+            CrackTimeSeconds::Float(f) => f,
+        };
+        Self {
+            seconds,
+            display: time.to_string(),
+        }
+    }
+}
+
+impl From<&Entropy> for StrengthReport {
+    fn from(entropy: &Entropy) -> Self {
+        let times = entropy.crack_times();
+        let feedback = entropy.feedback().as_ref();
+        Self {
+            score: entropy.score(),
+            guesses_log10: entropy.guesses_log10(),
+            entropy: None,
+            crack_times: CrackTimes {
+                online_throttled: times.online_throttling_100_per_hour().into(),
+                online_unthrottled: times.online_no_throttling_10_per_second().into(),
+                offline_slow_hashing: times.offline_slow_hashing_1e4_per_second().into(),
+                offline_fast_hashing: times.offline_fast_hashing_1e10_per_second().into(),
+            },
+            warning: feedback.and_then(|f| f.warning()).map(|w| w.to_string()),
+            suggestions: feedback
+                .map(|f| f.suggestions().iter().map(|s| s.to_string()).collect())
+                .unwrap_or_default(),
+        }
+    }
+}
+
+/// Estimate the strength of a password, `user_inputs` like names or emails count as words an
+/// attacker would try first.
+pub fn process_strength(password: &str, user_inputs: &[&str]) -> Result<StrengthReport> {
+    Ok(StrengthReport::from(&zxcvbn(password, user_inputs)?))
+}
+
+/// Generate candidates until one scores at least `min_score`, scored with `user_inputs` like
+/// the username the password is for.
+pub fn generate_scored<T: AsRef<str>>(
+    min_score: u8,
+    user_inputs: &[&str],
+    mut generate: impl FnMut() -> Result<T>,
+) -> Result<(T, StrengthReport)> {
+    for _ in 0..MAX_ATTEMPTS {
+        let candidate = generate()?;
+        let report = process_strength(candidate.as_ref(), user_inputs)?;
+        if report.score >= min_score {
+            return Ok((candidate, report));
+        }
+    }
+    bail!(
+        "no password scored {} or more in {} attempts, make them longer or allow more characters",
+        min_score,
+        MAX_ATTEMPTS
+    )
+}
+
+pub fn render_strength(report: &StrengthReport) -> String {
+    let mut res = String::new();
+    let times = &report.crack_times;
+    let _ = writeln!(res, "score:          {}/4", report.score);
+    let _ = writeln!(res, "guesses:        10^{:.1}", report.guesses_log10);
+    if let Some(entropy) = report.entropy {
+        let _ = writeln!(res, "entropy:        {:.1} bits", entropy);
+    }
+    res.push_str("crack times:\n");
+    let scenarios = [
+        ("online, 100/hour", &times.online_throttled),
+        ("online, 10/second", &times.online_unthrottled),
+        ("offline, 1e4/second", &times.offline_slow_hashing),
+        ("offline, 1e10/second", &times.offline_fast_hashing),
+    ];
+    for (scenario, time) in scenarios {
+        let _ = writeln!(res, "  {:<22}{}", scenario, time.display);
+    }
+    if let Some(warning) = &report.warning {
+        let _ = writeln!(res, "warning:        {}", warning);
+    }
+    for suggestion in &report.suggestions {
+        let _ = writeln!(res, "suggestion:     {}", suggestion);
+    }
+    res
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_strength_report() -> Result<()> {
+        let report = process_strength("juventus1897", &[])?;
+        assert!(report.score < 3);
+        assert!(report.guesses_log10 < 10.0);
+        let times = &report.crack_times;
+        assert!(times.online_throttled.seconds > times.offline_fast_hashing.seconds);
+        let text = render_strength(&report);
+        assert!(text.starts_with(&format!("score:          {}/4\n", report.score)));
+        assert!(text.contains("  offline, 1e10/second  less than a second\n"));
+
+        // the user inputs are guessed first
+        let report = process_strength("dybala", &["dybala"])?;
+        assert_eq!(report.score, 0);
+        Ok(())
+    }
+
+    #[test]
+    fn test_generate_scored() -> Result<()> {
+        let mut candidates = ["password", "123456", "correct-horse-battery-staple"].into_iter();
+        let (password, report) = generate_scored(4, &[], || Ok(candidates.next().unwrap()))?;
+        assert_eq!(password, "correct-horse-battery-staple");
+        assert_eq!(report.score, 4);
+
+        assert!(generate_scored(3, &[], || Ok("password")).is_err());
+        Ok(())
+    }
+}
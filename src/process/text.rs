@@ -8,7 +8,7 @@ use chacha20poly1305::{
 use ed25519_dalek::{Signature, Signer, SigningKey, Verifier, VerifyingKey};
 use rand::rngs::OsRng;
 
-use crate::{cli::TextSignFormat, get_reader, process_genpass};
+use crate::{cli::TextSignFormat, get_reader, process_genpass, PasswordPolicy};
 
 pub trait TextSign {
     fn sign(&self, reader: &mut dyn Read) -> anyhow::Result<Vec<u8>>;
@@ -208,7 +208,11 @@ impl KeyLoader for Ed25519Verifier {
 
 impl KeyGenerate for Blake3 {
     fn generate() -> anyhow::Result<Vec<Vec<u8>>> {
-        let key = process_genpass(32, true, true, true, true)?;
+        let policy = PasswordPolicy {
+            length: 32,
+            ..Default::default()
+        };
+        let key = process_genpass(&policy, &mut OsRng)?;
         let key = key.as_bytes().to_vec();
         Ok(vec![key])
     }
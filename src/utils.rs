@@ -1,4 +1,8 @@
-use std::{fs::File, io::Read};
+use std::{
+    fs::File,
+    io::{IsTerminal, Read, Write},
+    process::{Command, Stdio},
+};
 
 pub fn get_reader(input: &str) -> anyhow::Result<Box<dyn Read>> {
     let reader: Box<dyn Read> = if input == "-" {
@@ -8,3 +12,37 @@ pub fn get_reader(input: &str) -> anyhow::Result<Box<dyn Read>> {
     };
     Ok(reader)
 }
+
+pub fn get_writer(output: &str) -> anyhow::Result<Box<dyn Write>> {
+    let writer: Box<dyn Write> = if output == "-" {
+        Box::new(std::io::stdout())
+    } else {
+        Box::new(File::create(output)?)
+    };
+    Ok(writer)
+}
+
+/// Print `content` through `$PAGER` (`less` by default) when stdout is a terminal,
+/// or straight to stdout otherwise.
+pub fn print_paged(content: &str) -> anyhow::Result<()> {
+    if std::io::stdout().is_terminal() {
+        let pager = std::env::var("PAGER").unwrap_or_else(|_| "less -FRSX".to_string());
+        let mut args = pager.split_whitespace();
+        if let Some(program) = args.next() {
+            let child = Command::new(program)
+                .args(args)
+                .stdin(Stdio::piped())
+                .spawn();
+            if let Ok(mut child) = child {
+                if let Some(mut stdin) = child.stdin.take() {
+                    // the pager may quit before reading everything
+                    let _ = stdin.write_all(content.as_bytes());
+                }
+                child.wait()?;
+                return Ok(());
+            }
+        }
+    }
+    print!("{}", content);
+    Ok(())
+}